@@ -1,55 +1,150 @@
-use snafu::{ResultExt, Snafu};
-use std::collections::HashMap;
-type Result<T> = std::result::Result<T, AsmError>;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use snafu::{ensure, ResultExt, Snafu};
+type Result<T> = core::result::Result<T, AsmError>;
 
+/// Errors while parsing asmbunny code. Where available, `span` is the byte range of the
+/// offending text within the program passed to `parse_program`.
 #[derive(Debug, Snafu)]
 pub enum AsmError {
     #[snafu(display("Int format error for '{}': {}", data, source))]
     ParseInt {
         data: String,
-        source: std::num::ParseIntError,
+        source: core::num::ParseIntError,
+        span: Option<core::ops::Range<usize>>,
     },
 
     #[snafu(display("Invalid instruction '{}'", data))]
-    ParseInstruction { data: String },
+    ParseInstruction {
+        data: String,
+        span: Option<core::ops::Range<usize>>,
+    },
+
+    #[snafu(display("Invalid register '{}'", data))]
+    ParseRegister {
+        data: String,
+        span: Option<core::ops::Range<usize>>,
+    },
+
+    #[snafu(display("Instruction {} is outside of the program of length {}", index, len))]
+    InstructionOutOfRange { index: usize, len: usize },
+
+    #[cfg(feature = "std")]
+    #[snafu(display("I/O error: {}", source))]
+    Io { source: std::io::Error },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Source {
     Constant { value: i64 },
     Register { id: usize },
 }
 
-impl std::str::FromStr for Source {
+impl core::str::FromStr for Source {
     type Err = AsmError;
     fn from_str(s: &str) -> Result<Self> {
-        Ok(if let Ok(value) = s.parse::<i64>() {
-            Source::Constant { value }
-        } else {
-            Source::Register {
-                id: "abcdefghijklmnopqrstuvwxyz".find(s).unwrap(),
-            }
-        })
+        let looks_numeric = s
+            .chars()
+            .next()
+            .map(|c| c == '-' || c == '+' || c.is_ascii_digit())
+            .unwrap_or(false);
+
+        if looks_numeric {
+            let value = s.parse::<i64>().context(ParseInt {
+                data: s.to_string(),
+                span: None,
+            })?;
+            return Ok(Source::Constant { value });
+        }
+
+        // registers are single lowercase letters
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_lowercase() => Ok(Source::Register {
+                id: (c as u8 - b'a') as usize,
+            }),
+            _ => Err(AsmError::ParseRegister {
+                data: s.to_string(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl Source {
+    pub fn is_register(&self) -> bool {
+        matches!(self, Source::Register { .. })
+    }
+
+    pub fn is_constant(&self) -> bool {
+        matches!(self, Source::Constant { .. })
+    }
+
+    /// Get the index of the register, with 0 for `a`
+    pub fn as_register_id(&self) -> Option<usize> {
+        match self {
+            Source::Register { id } => Some(*id),
+            Source::Constant { .. } => None,
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self {
+            Source::Constant { value } => Some(*value),
+            Source::Register { .. } => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Source {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Source::Constant { value } => write!(f, "{}", value),
+            Source::Register { id } => write!(f, "{}", (b'a' + *id as u8) as char),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Instruction {
     // Cpy -> Jnz
-    Cpy { source: Source, register: Source },
+    Cpy {
+        source: Source,
+        register: Source,
+    },
     // Inc -> Dec
-    Inc { register: Source },
+    Inc {
+        register: Source,
+    },
     // Dec -> Inc
-    Dec { register: Source },
+    Dec {
+        register: Source,
+    },
     // Jnz -> Cpy
-    Jnz { source: Source, offset: Source },
+    Jnz {
+        source: Source,
+        offset: Source,
+    },
     // Tgl -> Inc
-    Tgl { offset: Source },
-    // Out -> Inc
-    Out { source: Source },
+    Tgl {
+        offset: Source,
+    },
+    // Out -> Inc. Without a channel name, values go to the `default` channel.
+    Out {
+        source: Source,
+        channel: Option<String>,
+    },
 }
 
-impl std::str::FromStr for Instruction {
+impl core::str::FromStr for Instruction {
     type Err = AsmError;
 
     fn from_str(s: &str) -> Result<Self> {
@@ -79,31 +174,617 @@ impl std::str::FromStr for Instruction {
             }
             &["out", source] => {
                 let source = source.parse()?;
-                Instruction::Out { source }
+                Instruction::Out {
+                    source,
+                    channel: None,
+                }
+            }
+            &["out", source, channel] => {
+                let source = source.parse()?;
+                Instruction::Out {
+                    source,
+                    channel: Some(channel.to_string()),
+                }
             }
             _ => {
                 return Err(AsmError::ParseInstruction {
                     data: s.to_string(),
+                    span: None,
                 })
             }
         })
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+impl Instruction {
+    /// Get the mnemonic of the instruction
+    pub fn opcode(&self) -> &'static str {
+        match self {
+            Instruction::Cpy { .. } => "cpy",
+            Instruction::Inc { .. } => "inc",
+            Instruction::Dec { .. } => "dec",
+            Instruction::Jnz { .. } => "jnz",
+            Instruction::Tgl { .. } => "tgl",
+            Instruction::Out { .. } => "out",
+        }
+    }
+
+    /// Rough number of cycles needed to execute the instruction. Instructions that touch
+    /// the program (`tgl`) or the outside world (`out`) are more expensive than register
+    /// arithmetic.
+    pub fn cost(&self) -> u32 {
+        match self {
+            Instruction::Cpy { .. }
+            | Instruction::Inc { .. }
+            | Instruction::Dec { .. }
+            | Instruction::Jnz { .. } => 1,
+            Instruction::Out { .. } => 2,
+            Instruction::Tgl { .. } => 4,
+        }
+    }
+
+    /// All operands of the instruction, in textual order
+    pub fn sources(&self) -> Vec<&Source> {
+        match self {
+            Instruction::Cpy { source, register } => vec![source, register],
+            Instruction::Inc { register } => vec![register],
+            Instruction::Dec { register } => vec![register],
+            Instruction::Jnz { source, offset } => vec![source, offset],
+            Instruction::Tgl { offset } => vec![offset],
+            Instruction::Out { source, .. } => vec![source],
+        }
+    }
+
+    /// Mutable references to all operands of the instruction, in textual order
+    pub fn sources_mut(&mut self) -> Vec<&mut Source> {
+        match self {
+            Instruction::Cpy { source, register } => vec![source, register],
+            Instruction::Inc { register } => vec![register],
+            Instruction::Dec { register } => vec![register],
+            Instruction::Jnz { source, offset } => vec![source, offset],
+            Instruction::Tgl { offset } => vec![offset],
+            Instruction::Out { source, .. } => vec![source],
+        }
+    }
+}
+
+impl core::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Instruction::Cpy { source, register } => write!(f, "cpy {} {}", source, register),
+            Instruction::Inc { register } => write!(f, "inc {}", register),
+            Instruction::Dec { register } => write!(f, "dec {}", register),
+            Instruction::Jnz { source, offset } => write!(f, "jnz {} {}", source, offset),
+            Instruction::Tgl { offset } => write!(f, "tgl {}", offset),
+            Instruction::Out {
+                source,
+                channel: None,
+            } => write!(f, "out {}", source),
+            Instruction::Out {
+                source,
+                channel: Some(channel),
+            } => write!(f, "out {} {}", source, channel),
+        }
+    }
+}
+
+// instructions are serialized in their textual form for readability
+#[cfg(feature = "serde")]
+impl serde::Serialize for Instruction {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Instruction {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> core::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Match a loop adding `y` to `x`: `inc x; dec y; jnz y -2` or `dec y; inc x; jnz y -2`
+fn match_add(insts: &[Instruction]) -> Option<(&Source, &Source)> {
+    let (x, y) = match insts {
+        [Instruction::Inc { register: x }, Instruction::Dec { register: y }, ..]
+        | [Instruction::Dec { register: y }, Instruction::Inc { register: x }, ..] => (x, y),
+        _ => return None,
+    };
+
+    match insts.get(2)? {
+        Instruction::Jnz {
+            source,
+            offset: Source::Constant { value: -2 },
+        } if source == y && x.is_register() && y.is_register() && x != y => Some((x, y)),
+        _ => None,
+    }
+}
+
+/// Match a nested loop adding `y * z` to `x`: `inc x; dec y; jnz y -2; dec z; jnz z -5`
+fn match_multiply(insts: &[Instruction]) -> Option<(&Source, &Source, &Source)> {
+    if !matches!(insts.first()?, Instruction::Inc { .. }) {
+        return None;
+    }
+    let (x, y) = match_add(insts)?;
+
+    match (insts.get(3)?, insts.get(4)?) {
+        (
+            Instruction::Dec { register: z },
+            Instruction::Jnz {
+                source,
+                offset: Source::Constant { value: -5 },
+            },
+        ) if source == z && z.is_register() && z != x && z != y => Some((x, y, z)),
+        _ => None,
+    }
+}
+
+/// Parse a whole program with one instruction per line, skipping blank lines.
+///
+/// A line `.def NAME VALUE` defines a named constant: `NAME` is replaced by `VALUE` in
+/// the operands of all following instructions.
+pub fn parse_program(src: &str) -> Result<Vec<Instruction>> {
+    parse_program_with_flags(src, &BTreeSet::new())
+}
+
+/// Parse a whole program like `parse_program`, additionally supporting conditional
+/// blocks: lines between `#if FLAG` and `#endif` are only used if `FLAG` is in `flags`.
+/// Blocks can be nested.
+pub fn parse_program_with_flags(src: &str, flags: &BTreeSet<String>) -> Result<Vec<Instruction>> {
+    let mut symbols: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut instructions = Vec::new();
+
+    // whether each currently open `#if` block is enabled
+    let mut blocks: Vec<bool> = Vec::new();
+
+    // all tokens are slices of `src`, so their offsets can be recovered from their addresses
+    let offset = |token: &str| token.as_ptr() as usize - src.as_ptr() as usize;
+    let span = |first: &str, last: &str| offset(first)..offset(last) + last.len();
+
+    for line in src.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match &tokens[..] {
+            ["#if", flag] => {
+                blocks.push(flags.contains(*flag));
+                continue;
+            }
+            ["#endif"] if !blocks.is_empty() => {
+                blocks.pop();
+                continue;
+            }
+            ["#if", ..] | ["#endif", ..] => {
+                return Err(AsmError::ParseInstruction {
+                    data: line.to_string(),
+                    span: Some(span(tokens[0], tokens[tokens.len() - 1])),
+                })
+            }
+            _ => {}
+        }
+
+        if blocks.contains(&false) {
+            continue;
+        }
+
+        match &tokens[..] {
+            [] => {}
+            [".def", name, value] => {
+                value.parse::<i64>().context(ParseInt {
+                    data: value.to_string(),
+                    span: Some(span(value, value)),
+                })?;
+                symbols.insert(name, value);
+            }
+            [".def", ..] => {
+                return Err(AsmError::ParseInstruction {
+                    data: line.to_string(),
+                    span: Some(span(tokens[0], tokens[tokens.len() - 1])),
+                })
+            }
+            [opcode, operands @ ..] => {
+                let mut resolved: Vec<&str> = vec![opcode];
+                resolved.extend(operands.iter().map(|o| *symbols.get(o).unwrap_or(o)));
+
+                let line_span = span(tokens[0], tokens[tokens.len() - 1]);
+                // point at the operand that failed to parse
+                let operand_span = |data: &str| {
+                    tokens
+                        .iter()
+                        .zip(resolved.iter())
+                        .find(|(_, r)| **r == data)
+                        .map(|(t, _)| span(t, t))
+                        .unwrap_or(line_span.clone())
+                };
+                let inst = resolved.join(" ").parse().map_err(|e| match e {
+                    AsmError::ParseInt { data, source, .. } => AsmError::ParseInt {
+                        span: Some(operand_span(&data)),
+                        data,
+                        source,
+                    },
+                    AsmError::ParseRegister { data, .. } => AsmError::ParseRegister {
+                        span: Some(operand_span(&data)),
+                        data,
+                    },
+                    AsmError::ParseInstruction { data, .. } => AsmError::ParseInstruction {
+                        data,
+                        span: Some(line_span.clone()),
+                    },
+                    e => e,
+                })?;
+                instructions.push(inst);
+            }
+        }
+    }
+
+    if !blocks.is_empty() {
+        return Err(AsmError::ParseInstruction {
+            data: "#if without #endif".to_string(),
+            span: None,
+        });
+    }
+
+    Ok(instructions)
+}
+
+/// Run a program to completion after setting the given registers, e.g. `&[("c", 1)]`,
+/// and return the final value of register `a`. With `turbo`, multiplication loops are
+/// sped up using `multiply_patch`.
+pub fn run_with_seed(program: &[Instruction], seed: &[(&str, i64)], turbo: bool) -> i64 {
+    let mut state = State::from_slice(program);
+    for (register, value) in seed {
+        state[*register] = *value;
+    }
+
+    while !state.is_halted() {
+        if turbo {
+            state.step_turbo(multiply_patch);
+        } else {
+            state.step();
+        }
+    }
+
+    state["a"]
+}
+
+/// Speed patch that runs the multiplication loop
+/// `cpy s y; inc x; dec y; jnz y -2; dec z; jnz z -5` in a single step when the
+/// instruction counter is at its `inc`. The outer loop jumps back to the `cpy`, so `x`
+/// grows by `y` in the first pass and by `s` in all further passes.
+pub fn multiply_patch(state: &mut State) -> Option<StepResult> {
+    if state.ic < 1 {
+        return None;
+    }
+    let index = state.ic as usize;
+
+    let (x, y, z) = match_multiply(state.instructions.get(index..)?)?;
+    let s = match &state.instructions[index - 1] {
+        Instruction::Cpy { source, register } if register == y => source,
+        _ => return None,
+    };
+    if s == x || s == y || s == z {
+        return None;
+    }
+
+    let (x, y, z, s) = (x.clone(), y.clone(), z.clone(), s.clone());
+    let (vx, vy, vz, vs) = (
+        state.get_value(&x),
+        state.get_value(&y),
+        state.get_value(&z),
+        state.get_value(&s),
+    );
+
+    // loops that would not count down to zero are left to the interpreter
+    if vy <= 0 || vz <= 0 || vs <= 0 {
+        return None;
+    }
+
+    // products that do not fit into a register are left to the interpreter, too
+    let product = (vz - 1).checked_mul(vs)?.checked_add(vy)?.checked_add(vx)?;
+
+    state.set_value(&x, product);
+    state.set_value(&y, 0);
+    state.set_value(&z, 0);
+    state.ic += 5;
+
+    Some(StepResult::OkNoOutput)
+}
+
+/// Speed patch that runs the countdown loop `dec r; jnz r -1` in a single step when the
+/// instruction counter is at its `dec`, leaving `r` at zero.
+pub fn countdown_patch(state: &mut State) -> Option<StepResult> {
+    if state.ic < 0 {
+        return None;
+    }
+    let index = state.ic as usize;
+
+    let r = match state.instructions.get(index..index + 2)? {
+        [Instruction::Dec { register }, Instruction::Jnz {
+            source,
+            offset: Source::Constant { value: -1 },
+        }] if register == source && register.is_register() => register.clone(),
+        _ => return None,
+    };
+
+    // loops that would not count down to zero are left to the interpreter
+    if state.get_value(&r) <= 0 {
+        return None;
+    }
+
+    state.set_value(&r, 0);
+    state.ic += 2;
+
+    Some(StepResult::OkNoOutput)
+}
+
+/// Generate a random but syntactically valid program of `len` instructions. The same
+/// `seed` always gives the same program. Only registers `a` to `d` are used, and constant
+/// jump and toggle offsets stay within the length of the program.
+#[cfg(any(test, feature = "testing"))]
+pub fn random_program(len: usize, seed: u64) -> Vec<Instruction> {
+    // splitmix64, which is good enough for generating test programs
+    fn next(state: &mut u64, n: u64) -> u64 {
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) % n
+    }
+
+    fn register(rng: &mut u64) -> Source {
+        Source::Register {
+            id: next(rng, 4) as usize,
+        }
+    }
+
+    fn operand(rng: &mut u64, max: i64) -> Source {
+        if next(rng, 2) == 0 {
+            register(rng)
+        } else {
+            Source::Constant {
+                value: next(rng, 2 * max as u64 + 1) as i64 - max,
+            }
+        }
+    }
+
+    let mut rng = seed;
+    let bound = len as i64;
+    (0..len)
+        .map(|_| match next(&mut rng, 6) {
+            0 => Instruction::Cpy {
+                source: operand(&mut rng, 10),
+                register: register(&mut rng),
+            },
+            1 => Instruction::Inc {
+                register: register(&mut rng),
+            },
+            2 => Instruction::Dec {
+                register: register(&mut rng),
+            },
+            3 => Instruction::Jnz {
+                source: operand(&mut rng, 1),
+                offset: operand(&mut rng, bound),
+            },
+            4 => Instruction::Tgl {
+                offset: operand(&mut rng, bound),
+            },
+            _ => Instruction::Out {
+                source: register(&mut rng),
+                channel: None,
+            },
+        })
+        .collect()
+}
+
+/// The VM state. Comparing and hashing only considers the execution state, i.e. the
+/// instruction counter, registers, program and register modulus, so that loop detection
+/// finds repeated states. Watches and statistics like `ignored_writes` are left out.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct State {
     pub ic: i64,
     pub registers: Vec<i64>,
     pub instructions: Vec<Instruction>,
+    /// Number of writes to constants that were ignored, e.g. by toggled instructions
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ignored_writes: u64,
+    /// If positive, all register writes are taken modulo this value
+    #[cfg_attr(feature = "serde", serde(default))]
+    modulus: i64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watches: Watches,
+}
+
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.ic == other.ic
+            && self.registers == other.registers
+            && self.instructions == other.instructions
+            && self.modulus == other.modulus
+    }
+}
+
+impl Eq for State {}
+
+impl core::hash::Hash for State {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.ic.hash(state);
+        self.registers.hash(state);
+        self.instructions.hash(state);
+        self.modulus.hash(state);
+    }
+}
+
+/// Name of the output channel used by `out` instructions without a channel name
+pub const DEFAULT_CHANNEL: &str = "default";
+
+/// Values written by `out` instructions, by channel, as collected by
+/// `State::step_recording`. They are a record of past execution that grows with every
+/// output, so they are kept outside of `State`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputChannels(BTreeMap<String, Vec<i64>>);
+
+impl OutputChannels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get all values written to an output channel so far
+    pub fn get(&self, channel: &str) -> &[i64] {
+        self.0.get(channel).map(|v| &v[..]).unwrap_or(&[])
+    }
+
+    /// Add a value to an output channel
+    pub fn record(&mut self, channel: &str, value: i64) {
+        self.0.entry(channel.to_string()).or_default().push(value);
+    }
+}
+
+/// Conditions on register values registered with `State::add_watch`. They are debugging
+/// aids and not part of the execution state.
+#[derive(Clone, Default)]
+struct Watches(Vec<(usize, WatchCondition)>);
+
+type WatchCondition = Arc<dyn Fn(i64) -> bool + Send + Sync>;
+
+impl core::fmt::Debug for Watches {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|(id, _)| Source::Register { id: *id }.to_string()),
+            )
+            .finish()
+    }
+}
+
+/// A watch condition that held after an instruction wrote to its register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Instruction counter of the writing instruction
+    pub ic: i64,
+    pub register: String,
+    pub value: i64,
+}
+
+/// An optimization that replaces a slow part of a program with a fast native
+/// implementation. Returns `None` if the patch does not apply to the current state.
+pub type SpeedPatch = Box<dyn Fn(&mut State) -> Option<StepResult>>;
+
+/// Suspicious but legal constructs found by `State::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmWarning {
+    /// The instruction writes to a constant and has no effect
+    WriteToConstant { index: usize },
+    /// A `jnz` with a constant offset of 0 that loops forever once taken
+    JumpToSelf { index: usize },
+    /// A `jnz` or `tgl` with a constant offset that targets the outside of the program
+    TargetOutOfProgram { index: usize, target: i64 },
+}
+
+/// The first step where an execution diverged from a recorded trace, see
+/// `State::replay_against`. `None` means that the program halted or the trace ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayMismatch {
+    pub step: usize,
+    pub expected: Option<(i64, String)>,
+    pub actual: Option<(i64, String)>,
+}
+
+impl core::fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let show = |v: &Option<(i64, String)>| match v {
+            Some((ic, opcode)) => format!("{} {}", ic, opcode),
+            None => "end of program".to_string(),
+        };
+        write!(
+            f,
+            "Step {}: expected '{}', got '{}'",
+            self.step,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// Changes to the program that can be observed using `State::step_with_events`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepEvent {
+    InstructionToggled {
+        index: usize,
+        from: Instruction,
+        to: Instruction,
+    },
+}
+
+/// Where control can go after an instruction, as found by `State::control_flow_graph`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Successor {
+    /// Continue at the instruction with this index
+    Instruction(usize),
+    /// Leave the program, which halts execution
+    Halt,
+    /// A `jnz` with a register offset, whose target is only known at runtime
+    Dynamic,
+}
+
+/// A saved execution point of a `State` that can be restored later
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pub ic: i64,
+    pub registers: Vec<i64>,
+    pub instructions: Vec<Instruction>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StepResult {
     OutOfProgram,
     OkNoOutput,
     OkOutput { out: i64 },
+    OkToggle { index: i64, result: ToggleResult },
+}
+
+/// Coarse control flow effect of a step, as reported by `State::step_kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    /// The instruction counter was outside of the program, so nothing was executed
+    Halted,
+    /// Execution continues with the next instruction
+    Advanced,
+    /// A `jnz` moved the instruction counter somewhere other than the next instruction
+    Jumped,
+    /// A `tgl` instruction was executed, whether or not its target was in the program
+    Toggled,
+    /// A value was sent out
+    Output(i64),
+}
+
+/// Outcome of a `tgl` instruction.
+///
+/// Toggling can produce instructions that make no sense, e.g. a `jnz 1 2` becomes a
+/// `cpy 1 2` that writes into a constant. Such instructions are kept in the program and
+/// skipped over when executed, as the puzzle requires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToggleResult {
+    /// The target was outside of the program, so nothing happened
+    OutOfBounds,
+    /// The target instruction was replaced
+    Toggled { from: Instruction, to: Instruction },
 }
 
 impl State {
+    /// Create a state that runs a copy of `instructions`, leaving the original untouched
+    /// when the program toggles itself
+    pub fn from_slice(instructions: &[Instruction]) -> Self {
+        Self::from_instructions(instructions.to_vec())
+    }
+
     pub fn from_instructions(instructions: Vec<Instruction>) -> Self {
         let registers = (0..26).map(|_| 0).collect();
         State {
@@ -113,75 +794,602 @@ impl State {
         }
     }
 
-    pub fn get_instruction(&self, pos: i64) -> Option<Instruction> {
-        if pos < 0 {
-            return None;
-        }
-        if pos as usize >= self.instructions.len() {
-            return None;
+    /// Get the current program, including all modifications by `tgl`
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Take the current program, including all modifications by `tgl`
+    pub fn into_instructions(self) -> Vec<Instruction> {
+        self.instructions
+    }
+
+    /// Parse a program with conditional blocks, see `parse_program_with_flags`
+    pub fn parse_with_flags(src: &str, flags: &BTreeSet<String>) -> Result<Self> {
+        Ok(State::from_instructions(parse_program_with_flags(
+            src, flags,
+        )?))
+    }
+
+    /// Read and parse a program from a file, see `parse_program`
+    #[cfg(feature = "std")]
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let src = std::fs::read_to_string(path).context(Io)?;
+        Ok(State::from_instructions(parse_program(&src)?))
+    }
+
+    /// Watch a register, e.g. `"a"`, for values that match a condition. See `run_watched`.
+    pub fn add_watch<F: Fn(i64) -> bool + Send + Sync + 'static>(
+        &mut self,
+        register: &str,
+        cond: F,
+    ) {
+        self.watches.0.push((register_id(register), Arc::new(cond)));
+    }
+
+    /// Run the program until an instruction writes a value to a watched register that
+    /// matches the watch's condition. Returns `None` if the program halts first.
+    pub fn run_watched(&mut self) -> Option<WatchHit> {
+        while let Some(inst) = self.current_instruction() {
+            let ic = self.ic;
+            self.step();
+
+            let written = match &inst {
+                Instruction::Cpy { register, .. }
+                | Instruction::Inc { register }
+                | Instruction::Dec { register } => register.as_register_id(),
+                _ => None,
+            };
+            let written = match written {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let value = self.registers[written];
+            let hit = self
+                .watches
+                .0
+                .iter()
+                .any(|(id, cond)| *id == written && cond(value));
+            if hit {
+                return Some(WatchHit {
+                    ic,
+                    register: Source::Register { id: written }.to_string(),
+                    value,
+                });
+            }
         }
-        self.instructions.get(pos as usize).cloned()
+        None
     }
 
-    pub fn get_value(&mut self, source: &Source) -> i64 {
-        match source {
-            Source::Constant { value } => *value,
-            Source::Register { id } => self.registers[*id],
+    /// Replace the program and restart execution at its first instruction, keeping the
+    /// current register values
+    pub fn load_program(&mut self, instructions: Vec<Instruction>) {
+        self.instructions = instructions;
+        self.ic = 0;
+    }
+
+    /// Replace the instruction at `index` without changing anything else
+    pub fn patch_instruction(&mut self, index: usize, inst: Instruction) -> Result<()> {
+        let len = self.instructions.len();
+        ensure!(index < len, InstructionOutOfRange { index, len });
+
+        self.instructions[index] = inst;
+        Ok(())
+    }
+
+    /// Make registers wrap around at `modulus`, e.g. 256 for 8-bit registers. A modulus
+    /// of 0 (the default) leaves registers unbounded.
+    pub fn set_register_modulus(&mut self, modulus: i64) {
+        self.modulus = modulus;
+    }
+
+    /// Save the current execution point
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ic: self.ic,
+            registers: self.registers.clone(),
+            instructions: self.instructions.clone(),
         }
     }
 
-    pub fn set_value(&mut self, source: &Source, value: i64) {
-        match source {
-            Source::Constant { .. } => { /* ignore setting to a constant */ }
-            Source::Register { id } => {
-                self.registers[*id] = value;
+    /// Roll back to a previously saved execution point
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.ic = snapshot.ic;
+        self.registers.clone_from(&snapshot.registers);
+        self.instructions.clone_from(&snapshot.instructions);
+    }
+
+    /// Rename the registers to `a`, `b`, `c`, ... in the order they first appear in the
+    /// program. Register values are moved along so that the program behaves the same.
+    pub fn canonicalize_registers(&mut self) {
+        let mut mapping: Vec<Option<usize>> = vec![None; self.registers.len()];
+        let mut next = 0;
+
+        for inst in self.instructions.iter_mut() {
+            for source in inst.sources_mut() {
+                if let Source::Register { id } = source {
+                    *id = match mapping[*id] {
+                        Some(new) => new,
+                        None => {
+                            mapping[*id] = Some(next);
+                            next += 1;
+                            next - 1
+                        }
+                    };
+                }
             }
         }
+
+        // registers that are never used keep their relative order after the used ones
+        let mut registers = vec![0; self.registers.len()];
+        for (old, new) in mapping.iter().enumerate() {
+            let new = new.unwrap_or_else(|| {
+                next += 1;
+                next - 1
+            });
+            registers[new] = self.registers[old];
+        }
+        self.registers = registers;
     }
 
-    pub fn step_turbo<F: Fn(&mut Self) -> Option<StepResult>>(
-        &mut self,
-        speed_patch: F,
-    ) -> StepResult {
-        if let Some(ret) = speed_patch(self) {
-            ret
-        } else {
-            self.step()
+    /// Produce a listing of the program where recognized idioms are marked with comments
+    pub fn annotate(&self) -> String {
+        let mut out = String::new();
+        for (i, inst) in self.instructions.iter().enumerate() {
+            let line = format!("{:3}: {}", i, inst);
+            match self.describe_idiom(i) {
+                Some(note) => out.push_str(&format!("{:<16}; {}\n", line, note)),
+                None => out.push_str(&format!("{}\n", line)),
+            }
         }
+        out
     }
 
-    pub fn step(&mut self) -> StepResult {
-        let inst = self.get_instruction(self.ic);
-        if inst.is_none() {
-            return StepResult::OutOfProgram;
+    /// Describe a known idiom starting at the instruction at `index`, if any
+    fn describe_idiom(&self, index: usize) -> Option<String> {
+        let insts = &self.instructions[index..];
+
+        if let Some((x, y, z)) = match_multiply(insts) {
+            return Some(format!("multiply: {} += {}*{}", x, y, z));
         }
 
-        match inst.unwrap() {
-            Instruction::Cpy { source, register } => {
-                let value = self.get_value(&source);
-                self.set_value(&register, value);
+        if let Some((x, y)) = match_add(insts) {
+            return Some(format!("add: {} += {}", x, y));
+        }
+
+        match insts.first()? {
+            Instruction::Tgl {
+                offset: Source::Constant { value },
+            } if self.get_instruction(index as i64 + value).is_none() => {
+                Some("no-op: toggles outside of program".to_string())
             }
-            Instruction::Inc { register } => {
-                let value = self.get_value(&register);
-                self.set_value(&register, value + 1);
+            Instruction::Cpy {
+                register: Source::Constant { .. },
+                ..
             }
-            Instruction::Dec { register } => {
-                let value = self.get_value(&register);
-                self.set_value(&register, value - 1);
+            | Instruction::Inc {
+                register: Source::Constant { .. },
             }
-            Instruction::Jnz { source, offset } => {
-                let value = self.get_value(&source);
-                let ofs = self.get_value(&offset);
-                if value != 0 {
+            | Instruction::Dec {
+                register: Source::Constant { .. },
+            } => Some("no-op: writes to a constant".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Check the program for instructions that are legal but most likely not intended.
+    /// Since toggling can turn such instructions into useful ones, these are only warnings.
+    pub fn validate(&self) -> core::result::Result<(), Vec<AsmWarning>> {
+        let mut warnings = Vec::new();
+
+        for (index, inst) in self.instructions.iter().enumerate() {
+            let target_of = |offset: &Source| offset.as_constant().map(|v| index as i64 + v);
+
+            match inst {
+                Instruction::Cpy {
+                    register: Source::Constant { .. },
+                    ..
+                }
+                | Instruction::Inc {
+                    register: Source::Constant { .. },
+                }
+                | Instruction::Dec {
+                    register: Source::Constant { .. },
+                } => warnings.push(AsmWarning::WriteToConstant { index }),
+                Instruction::Jnz {
+                    offset: Source::Constant { value: 0 },
+                    ..
+                } => warnings.push(AsmWarning::JumpToSelf { index }),
+                Instruction::Jnz { offset, .. } | Instruction::Tgl { offset } => {
+                    if let Some(target) = target_of(offset) {
+                        if self.get_instruction(target).is_none() {
+                            warnings.push(AsmWarning::TargetOutOfProgram { index, target });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if warnings.is_empty() {
+            Ok(())
+        } else {
+            Err(warnings)
+        }
+    }
+
+    /// Find the indices of all instructions that can be reached from the first one by
+    /// following the control flow. Both branches of a `jnz` are followed unless its
+    /// condition is a constant. A `jnz` with a register offset can jump anywhere, so it
+    /// makes all instructions reachable. Instructions changed by `tgl` are not considered.
+    pub fn reachable_instructions(&self) -> BTreeSet<usize> {
+        let mut reachable = BTreeSet::new();
+        let mut stack = vec![0i64];
+
+        while let Some(index) = stack.pop() {
+            if index < 0 || index as usize >= self.instructions.len() {
+                continue;
+            }
+            if !reachable.insert(index as usize) {
+                continue;
+            }
+
+            match &self.instructions[index as usize] {
+                Instruction::Jnz { source, offset } => {
+                    let target = match offset.as_constant() {
+                        Some(ofs) => index + ofs,
+                        None => return (0..self.instructions.len()).collect(),
+                    };
+                    match source.as_constant() {
+                        Some(0) => stack.push(index + 1),
+                        Some(_) => stack.push(target),
+                        None => {
+                            stack.push(index + 1);
+                            stack.push(target);
+                        }
+                    }
+                }
+                _ => stack.push(index + 1),
+            }
+        }
+
+        reachable
+    }
+
+    /// Build the static control flow graph of the program, listing the possible successors
+    /// of every instruction. A `jnz` with a constant condition only has the edge it will
+    /// take. As with `reachable_instructions`, changes made by `tgl` are not considered.
+    pub fn control_flow_graph(&self) -> Vec<(usize, Vec<Successor>)> {
+        let len = self.instructions.len() as i64;
+        let successor = |target: i64| {
+            if target < 0 || target >= len {
+                Successor::Halt
+            } else {
+                Successor::Instruction(target as usize)
+            }
+        };
+
+        self.instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| {
+                let next = successor(index as i64 + 1);
+                let mut successors = match instruction {
+                    Instruction::Jnz { source, offset } => {
+                        let target = match offset.as_constant() {
+                            Some(ofs) => successor(index as i64 + ofs),
+                            None => Successor::Dynamic,
+                        };
+                        match source.as_constant() {
+                            Some(0) => vec![next],
+                            Some(_) => vec![target],
+                            None => vec![next, target],
+                        }
+                    }
+                    _ => vec![next],
+                };
+                successors.sort();
+                successors.dedup();
+                (index, successors)
+            })
+            .collect()
+    }
+
+    /// Check whether the instruction counter has left the program
+    pub fn is_halted(&self) -> bool {
+        self.current_instruction().is_none()
+    }
+
+    /// The instruction that will be executed by the next step, if any
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.get_instruction(self.ic)
+    }
+
+    pub fn get_instruction(&self, pos: i64) -> Option<Instruction> {
+        if pos < 0 {
+            return None;
+        }
+        if pos as usize >= self.instructions.len() {
+            return None;
+        }
+        self.instructions.get(pos as usize).cloned()
+    }
+
+    /// Get the names and values of all registers in alphabetical order
+    pub fn registers_view(&self) -> Vec<(String, i64)> {
+        self.registers
+            .iter()
+            .enumerate()
+            .map(|(id, v)| (Source::Register { id }.to_string(), *v))
+            .collect()
+    }
+
+    /// Check whether two states have the same instruction counter, program and register
+    /// values, treating registers that are missing from either state as zero. Watches
+    /// and statistics like `ignored_writes` are not compared.
+    pub fn semantically_eq(&self, other: &State) -> bool {
+        let len = self.registers.len().max(other.registers.len());
+        let value = |registers: &[i64], id: usize| registers.get(id).copied().unwrap_or(0);
+
+        self.ic == other.ic
+            && self.instructions == other.instructions
+            && (0..len).all(|id| value(&self.registers, id) == value(&other.registers, id))
+    }
+
+    pub fn get_value(&self, source: &Source) -> i64 {
+        match source {
+            Source::Constant { value } => *value,
+            Source::Register { id } => self.registers[*id],
+        }
+    }
+
+    pub fn set_value(&mut self, source: &Source, value: i64) {
+        match source {
+            Source::Constant { .. } => {
+                // writes to constants are legal since `tgl` can produce them
+                self.ignored_writes += 1;
+            }
+            Source::Register { id } => {
+                self.registers[*id] = if self.modulus > 0 {
+                    value.rem_euclid(self.modulus)
+                } else {
+                    value
+                };
+            }
+        }
+    }
+
+    /// Lazily execute the program, yielding each executed instruction with the
+    /// instruction counter it was executed at until the program halts
+    pub fn iter_steps(&mut self) -> impl Iterator<Item = (i64, Instruction)> + '_ {
+        core::iter::from_fn(move || {
+            let ic = self.ic;
+            let inst = self.current_instruction()?;
+            self.step();
+            Some((ic, inst))
+        })
+    }
+
+    /// Run the program to completion, returning the number of steps and how often each
+    /// opcode was executed. Toggled instructions are counted with their new opcode.
+    pub fn run_to_halt_counting(&mut self) -> (usize, BTreeMap<&'static str, usize>) {
+        let mut steps = 0;
+        let mut counts = BTreeMap::new();
+
+        while let Some(inst) = self.current_instruction() {
+            *counts.entry(inst.opcode()).or_insert(0) += 1;
+            steps += 1;
+            self.step();
+        }
+
+        (steps, counts)
+    }
+
+    /// Run the program to completion, returning the total `Instruction::cost` of all
+    /// executed instructions. Toggled instructions are weighted with their new cost.
+    pub fn run_counting_cycles(&mut self) -> u64 {
+        let mut cycles = 0;
+        while let Some(inst) = self.current_instruction() {
+            cycles += u64::from(inst.cost());
+            self.step();
+        }
+        cycles
+    }
+
+    /// Run a copy of the state for up to `max_steps` steps, looking for an execution point
+    /// that repeats. Returns the step at which the cycle starts and its length, or `None`
+    /// if the program halts or no repetition was found. The program is part of every
+    /// fingerprint so that programs which keep toggling themselves are only reported once
+    /// they return to an earlier version. This means that a copy of all instructions is
+    /// kept for every step. Outputs are not considered.
+    pub fn find_cycle(&self, max_steps: usize) -> Option<(usize, usize)> {
+        let mut state = self.clone();
+        let mut seen: BTreeMap<Snapshot, usize> = BTreeMap::new();
+
+        for step in 0..max_steps {
+            if let Some(start) = seen.insert(state.snapshot(), step) {
+                return Some((start, step - start));
+            }
+            if state.step() == StepResult::OutOfProgram {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    /// Run the program to completion, writing a trace with one line per step that contains
+    /// the instruction counter, the opcode and all registers that changed, e.g. `4 inc a=3`
+    #[cfg(feature = "std")]
+    pub fn run_logging<W: std::io::Write>(&mut self, out: &mut W) -> std::io::Result<()> {
+        while let Some(inst) = self.current_instruction() {
+            let ic = self.ic;
+            let before = self.registers.clone();
+            self.step();
+
+            write!(out, "{} {}", ic, inst.opcode())?;
+            for (id, (old, new)) in before.iter().zip(self.registers.iter()).enumerate() {
+                if old != new {
+                    write!(out, " {}={}", Source::Register { id }, new)?;
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Run the program while checking each executed instruction counter and opcode against
+    /// a recorded trace. The program must halt exactly when the trace ends.
+    pub fn replay_against(
+        &mut self,
+        log: &[(i64, String)],
+    ) -> core::result::Result<(), ReplayMismatch> {
+        for step in 0..=log.len() {
+            let expected = log.get(step).cloned();
+            let actual = self
+                .current_instruction()
+                .map(|inst| (self.ic, inst.opcode().to_string()));
+
+            if expected != actual {
+                return Err(ReplayMismatch {
+                    step,
+                    expected,
+                    actual,
+                });
+            }
+
+            self.step();
+        }
+        Ok(())
+    }
+
+    /// Check that running the program to completion from the current state gives the same
+    /// registers with and without speed patches. Both runs must terminate.
+    pub fn verify_optimization(&self, speed_patches: &[SpeedPatch]) -> bool {
+        let mut plain = self.clone();
+        while !plain.is_halted() {
+            plain.step();
+        }
+
+        let mut optimized = self.clone();
+        while !optimized.is_halted() {
+            optimized.step_turbo_multi(speed_patches);
+        }
+
+        plain.registers == optimized.registers
+    }
+
+    pub fn step_turbo<F: Fn(&mut Self) -> Option<StepResult>>(
+        &mut self,
+        speed_patch: F,
+    ) -> StepResult {
+        if let Some(ret) = speed_patch(self) {
+            ret
+        } else {
+            self.step()
+        }
+    }
+
+    /// Execute a step, skipping over countdown loops using `countdown_patch`
+    pub fn step_accelerated(&mut self) -> StepResult {
+        self.step_turbo(countdown_patch)
+    }
+
+    /// Try several speed patches in order, using the first one that applies. If none
+    /// apply, execute a normal step.
+    pub fn step_turbo_multi(&mut self, speed_patches: &[SpeedPatch]) -> StepResult {
+        for speed_patch in speed_patches {
+            if let Some(ret) = speed_patch(self) {
+                return ret;
+            }
+        }
+        self.step()
+    }
+
+    /// Execute a step, adding any value sent out to its channel in `outputs`
+    pub fn step_recording(&mut self, outputs: &mut OutputChannels) -> StepResult {
+        let channel = match self.current_instruction() {
+            Some(Instruction::Out {
+                channel: Some(channel),
+                ..
+            }) => channel,
+            _ => DEFAULT_CHANNEL.to_string(),
+        };
+
+        let result = self.step();
+        if let StepResult::OkOutput { out } = result {
+            outputs.record(&channel, out);
+        }
+        result
+    }
+
+    /// Execute a step like `step`, but only report how it affected the control flow
+    pub fn step_kind(&mut self) -> StepKind {
+        let ic = self.ic;
+        match self.step() {
+            StepResult::OutOfProgram => StepKind::Halted,
+            StepResult::OkOutput { out } => StepKind::Output(out),
+            StepResult::OkToggle { .. } => StepKind::Toggled,
+            StepResult::OkNoOutput if self.ic != ic + 1 => StepKind::Jumped,
+            StepResult::OkNoOutput => StepKind::Advanced,
+        }
+    }
+
+    /// Execute a step, reporting any modifications to the program to a callback
+    pub fn step_with_events<F: FnMut(StepEvent)>(&mut self, mut callback: F) -> StepResult {
+        let result = self.step();
+
+        if let StepResult::OkToggle {
+            index,
+            result: ToggleResult::Toggled { from, to },
+        } = &result
+        {
+            callback(StepEvent::InstructionToggled {
+                index: *index as usize,
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+
+        result
+    }
+
+    pub fn step(&mut self) -> StepResult {
+        let inst = self.current_instruction();
+        if inst.is_none() {
+            return StepResult::OutOfProgram;
+        }
+
+        match inst.unwrap() {
+            Instruction::Cpy { source, register } => {
+                let value = self.get_value(&source);
+                self.set_value(&register, value);
+            }
+            Instruction::Inc { register } => {
+                let value = self.get_value(&register);
+                self.set_value(&register, value + 1);
+            }
+            Instruction::Dec { register } => {
+                let value = self.get_value(&register);
+                self.set_value(&register, value - 1);
+            }
+            Instruction::Jnz { source, offset } => {
+                let value = self.get_value(&source);
+                let ofs = self.get_value(&offset);
+                if value != 0 {
                     self.ic += ofs;
                     return StepResult::OkNoOutput;
                 }
             }
             Instruction::Tgl { offset } => {
+                // the target is computed once and only written if it lies inside the
+                // program, huge offsets saturate instead of overflowing
                 let ofs = self.get_value(&offset);
+                let index = self.ic.saturating_add(ofs);
 
-                if let Some(inst) = self.get_instruction(self.ic + ofs) {
-                    let new_inst: Instruction = match inst {
+                let result = if let Some(inst) = self.get_instruction(index) {
+                    let new_inst: Instruction = match &inst {
                         Instruction::Cpy { source, register } => Instruction::Jnz {
                             source: source.clone(),
                             offset: register.clone(),
@@ -199,14 +1407,23 @@ impl State {
                         Instruction::Tgl { offset } => Instruction::Inc {
                             register: offset.clone(),
                         },
-                        Instruction::Out { source } => Instruction::Inc {
+                        Instruction::Out { source, .. } => Instruction::Inc {
                             register: source.clone(),
                         },
                     };
-                    self.instructions[(self.ic + ofs) as usize] = new_inst;
-                }
+                    self.instructions[index as usize] = new_inst.clone();
+                    ToggleResult::Toggled {
+                        from: inst,
+                        to: new_inst,
+                    }
+                } else {
+                    ToggleResult::OutOfBounds
+                };
+
+                self.ic += 1;
+                return StepResult::OkToggle { index, result };
             }
-            Instruction::Out { source } => {
+            Instruction::Out { source, .. } => {
                 let out = self.get_value(&source);
                 self.ic += 1;
                 return StepResult::OkOutput { out };
@@ -216,3 +1433,939 @@ impl State {
         StepResult::OkNoOutput
     }
 }
+
+/// Get the index of a register from its name, panicking for invalid names
+fn register_id(name: &str) -> usize {
+    match name.parse() {
+        Ok(Source::Register { id }) => id,
+        _ => panic!("Invalid register '{}'", name),
+    }
+}
+
+impl core::ops::Index<&str> for State {
+    type Output = i64;
+
+    fn index(&self, name: &str) -> &i64 {
+        &self.registers[register_id(name)]
+    }
+}
+
+impl core::ops::IndexMut<&str> for State {
+    fn index_mut(&mut self, name: &str) -> &mut i64 {
+        &mut self.registers[register_id(name)]
+    }
+}
+
+impl core::iter::FromIterator<Instruction> for State {
+    fn from_iter<I: IntoIterator<Item = Instruction>>(iter: I) -> Self {
+        State::from_instructions(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(
+            "-3".parse::<Source>().unwrap(),
+            Source::Constant { value: -3 }
+        );
+        assert_eq!("c".parse::<Source>().unwrap(), Source::Register { id: 2 });
+
+        match "abc".parse::<Source>() {
+            Err(AsmError::ParseRegister { data, span }) => {
+                assert_eq!(data, "abc");
+                assert_eq!(span, None);
+            }
+            other => panic!("Expected register error, got {:?}", other),
+        }
+
+        match "12a".parse::<Source>() {
+            Err(AsmError::ParseInt { data, .. }) => assert_eq!(data, "12a"),
+            other => panic!("Expected int error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_source_accessors() {
+        let register: Source = "c".parse().unwrap();
+        let constant: Source = "-7".parse().unwrap();
+
+        assert!(register.is_register());
+        assert!(!constant.is_register());
+
+        assert!(constant.is_constant());
+        assert!(!register.is_constant());
+
+        assert_eq!(register.as_register_id(), Some(2));
+        assert_eq!(constant.as_register_id(), None);
+
+        assert_eq!(constant.as_constant(), Some(-7));
+        assert_eq!(register.as_constant(), None);
+    }
+
+    fn program(src: &str) -> Vec<Instruction> {
+        parse_program(src).unwrap()
+    }
+
+    #[test]
+    fn test_parse_program() -> Result<()> {
+        let instructions = parse_program("cpy 1 a\n\n   \n  inc a\n\tdec b\n\n")?;
+        assert_eq!(
+            instructions,
+            vec!["cpy 1 a".parse()?, "inc a".parse()?, "dec b".parse()?]
+        );
+
+        assert!(parse_program("cpy 1 a\nfoo").is_err());
+        assert!("".parse::<Instruction>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_program_defs() -> Result<()> {
+        let instructions =
+            parse_program(".def seed 7\n.def step -1\ncpy seed a\ninc b\njnz a step")?;
+        assert_eq!(
+            instructions,
+            vec!["cpy 7 a".parse()?, "inc b".parse()?, "jnz a -1".parse()?]
+        );
+
+        let mut state = State::from_instructions(instructions);
+        state.step();
+        assert_eq!(state.registers[0], 7);
+
+        // unknown symbols are still parsed as registers
+        assert_eq!(parse_program(".def seed 7\ncpy c a")?, program("cpy c a"));
+
+        assert!(parse_program(".def seed\ncpy seed a").is_err());
+        assert!(parse_program(".def seed a\ncpy seed a").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_program_spans() {
+        let src = "cpy 1 a\n  jnz 1x -1\nfoo a b\n";
+
+        match parse_program(src) {
+            Err(AsmError::ParseInt { data, span, .. }) => {
+                assert_eq!(data, "1x");
+                assert_eq!(span, Some(14..16));
+                assert_eq!(&src[14..16], "1x");
+            }
+            other => panic!("Expected int error, got {:?}", other),
+        }
+
+        let src = "cpy 1 a\ncpy ab a\n";
+        match parse_program(src) {
+            Err(AsmError::ParseRegister { data, span }) => {
+                assert_eq!(data, "ab");
+                assert_eq!(span, Some(12..14));
+                assert_eq!(&src[12..14], "ab");
+            }
+            other => panic!("Expected register error, got {:?}", other),
+        }
+
+        match parse_program("inc a\n  inc xy") {
+            Err(AsmError::ParseRegister { span, .. }) => assert_eq!(span, Some(12..14)),
+            other => panic!("Expected register error, got {:?}", other),
+        }
+
+        match parse_program("inc a\nfoo a b") {
+            Err(AsmError::ParseInstruction { span, .. }) => assert_eq!(span, Some(6..13)),
+            other => panic!("Expected instruction error, got {:?}", other),
+        }
+
+        match "foo a b".parse::<Instruction>() {
+            Err(AsmError::ParseInstruction { span, .. }) => assert_eq!(span, None),
+            other => panic!("Expected instruction error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_with_flags() -> Result<()> {
+        let src = "cpy 7 a\n#if part2\ncpy 12 a\n#if debug\nout a\n#endif\n#endif\ninc a";
+
+        let part1 = State::parse_with_flags(src, &BTreeSet::new())?;
+        assert_eq!(part1.instructions, program("cpy 7 a\ninc a"));
+
+        let flags: BTreeSet<String> = ["part2".to_string()].iter().cloned().collect();
+        let part2 = State::parse_with_flags(src, &flags)?;
+        assert_eq!(part2.instructions, program("cpy 7 a\ncpy 12 a\ninc a"));
+
+        assert!(parse_program("#if part2\ninc a").is_err());
+        assert!(parse_program("inc a\n#endif").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_state() -> Result<()> {
+        let state: State = "inc a\ndec b"
+            .lines()
+            .map(|l| l.parse())
+            .collect::<Result<_>>()?;
+
+        assert_eq!(state, State::from_instructions(program("inc a\ndec b")));
+        assert_eq!(state.registers.len(), 26);
+
+        assert!("inc a\nfoo"
+            .lines()
+            .map(|l| l.parse())
+            .collect::<Result<State>>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_halted() {
+        let mut state = State::from_instructions(program("inc a\ninc b"));
+        assert!(!state.is_halted());
+
+        state.ic = 1;
+        assert!(!state.is_halted());
+
+        state.ic = 2;
+        assert!(state.is_halted());
+
+        state.ic = -1;
+        assert!(state.is_halted());
+    }
+
+    #[test]
+    fn test_instruction_sources() {
+        let instructions = program("cpy 3 d\ninc b\njnz d -1\ntgl c\nout 4");
+
+        let touched: BTreeSet<String> = instructions
+            .iter()
+            .flat_map(|inst| inst.sources())
+            .filter(|s| s.is_register())
+            .map(|s| s.to_string())
+            .collect();
+        let names: Vec<&str> = touched.iter().map(|s| s.as_str()).collect();
+        assert_eq!(names, vec!["b", "c", "d"]);
+
+        let mut inst = instructions[0].clone();
+        for source in inst.sources_mut() {
+            if let Source::Constant { value } = source {
+                *value += 1;
+            }
+        }
+        assert_eq!(inst, "cpy 4 d".parse().unwrap());
+    }
+
+    #[test]
+    fn test_canonicalize_registers() {
+        let mut first = State::from_instructions(program("cpy 3 d\ninc b\ndec d\njnz d -2"));
+        let mut second = State::from_instructions(program("cpy 3 c\ninc a\ndec c\njnz c -2"));
+        first["b"] = 7;
+        second["a"] = 7;
+
+        first.canonicalize_registers();
+        second.canonicalize_registers();
+
+        assert_eq!(first, second);
+        assert_eq!(
+            first.instructions,
+            program("cpy 3 a\ninc b\ndec a\njnz a -2")
+        );
+        assert_eq!(first["b"], 7);
+    }
+
+    #[test]
+    fn test_register_modulus() {
+        let mut state = State::from_instructions(program("cpy 255 a\ninc a\ndec b\ncpy 300 c"));
+        state.set_register_modulus(256);
+        while !state.is_halted() {
+            state.step();
+        }
+
+        assert_eq!(state["a"], 0);
+        assert_eq!(state["b"], 255);
+        assert_eq!(state["c"], 44);
+    }
+
+    #[test]
+    fn test_replay_against() {
+        let log: Vec<(i64, String)> = [(0, "cpy"), (1, "dec"), (2, "jnz"), (1, "dec"), (2, "jnz")]
+            .iter()
+            .map(|(ic, op)| (*ic, op.to_string()))
+            .collect();
+        let instructions = program("cpy 2 a\ndec a\njnz a -1");
+
+        let mut state = State::from_instructions(instructions.clone());
+        assert_eq!(state.replay_against(&log), Ok(()));
+
+        let mut state = State::from_instructions(instructions.clone());
+        state.instructions[0] = "cpy 3 a".parse().unwrap();
+        assert_eq!(
+            state.replay_against(&log),
+            Err(ReplayMismatch {
+                step: 5,
+                expected: None,
+                actual: Some((1, "dec".to_string())),
+            })
+        );
+
+        let mut state = State::from_instructions(instructions);
+        assert_eq!(
+            state.replay_against(&log[..2]),
+            Err(ReplayMismatch {
+                step: 2,
+                expected: None,
+                actual: Some((2, "jnz".to_string())),
+            })
+        );
+    }
+
+    #[test]
+    fn test_registers_view() {
+        let mut state = State::from_instructions(program("cpy 4 b"));
+        state.step();
+
+        let before = state.clone();
+        let view = state.registers_view();
+        assert_eq!(state, before);
+
+        assert_eq!(view.len(), 26);
+        assert_eq!(view[0], ("a".to_string(), 0));
+        assert_eq!(view[1], ("b".to_string(), 4));
+        assert_eq!(view[25].0, "z");
+    }
+
+    #[test]
+    fn test_semantically_eq() {
+        let mut explicit = State::from_instructions(program("cpy 4 b\ncpy 0 x"));
+        explicit.step();
+        explicit.step();
+
+        let mut omitted = explicit.clone();
+        omitted.registers.truncate(2);
+        omitted.ignored_writes = 3;
+        assert_ne!(explicit, omitted);
+        assert!(explicit.semantically_eq(&omitted));
+        assert!(omitted.semantically_eq(&explicit));
+
+        explicit["x"] = 1;
+        assert!(!explicit.semantically_eq(&omitted));
+    }
+
+    #[test]
+    fn test_run_with_seed() {
+        let multiply = program("cpy b c\ninc a\ndec c\njnz c -2\ndec d\njnz d -5");
+
+        assert_eq!(run_with_seed(&program("inc a\ninc a"), &[], false), 2);
+        assert_eq!(run_with_seed(&multiply, &[("b", 6), ("d", 7)], false), 42);
+        assert_eq!(run_with_seed(&multiply, &[("b", 6), ("d", 7)], true), 42);
+        assert_eq!(
+            run_with_seed(&multiply, &[("a", 1), ("b", 2), ("d", 2)], true),
+            5
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_file() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("asmbunny-{}.txt", std::process::id()));
+        std::fs::write(&path, "cpy 2 a\ninc a\n").unwrap();
+
+        let state = State::from_file(&path)?;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(state, State::from_instructions(program("cpy 2 a\ninc a")));
+
+        assert!(matches!(State::from_file(&path), Err(AsmError::Io { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_watched() {
+        let mut state = State::from_instructions(program("cpy 40 a\ninc a\ninc b\ninc a\ninc a"));
+        state.add_watch("a", |v| v == 42);
+        state.add_watch("b", |v| v < 0);
+        assert_eq!(state, State::from_instructions(state.instructions.clone()));
+
+        assert_eq!(
+            state.run_watched(),
+            Some(WatchHit {
+                ic: 3,
+                register: "a".to_string(),
+                value: 42
+            })
+        );
+        assert_eq!(state.ic, 4);
+
+        // the condition only triggers again once a is written to
+        assert_eq!(state.run_watched(), None);
+        assert_eq!(state["a"], 43);
+    }
+
+    #[test]
+    fn test_watched_state_is_send() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut state = State::from_instructions(program("inc a"));
+        state.add_watch("a", |v| v > 0);
+        assert_send_sync(&state);
+
+        let handle = std::thread::spawn(move || state.run_watched());
+        assert!(handle.join().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reachable_instructions() {
+        let state = State::from_instructions(program(
+            "cpy 2 a\njnz 1 2\ninc b\ndec a\njnz a -1\njnz 1 10\ninc c\ninc d",
+        ));
+        let reachable: Vec<usize> = state.reachable_instructions().into_iter().collect();
+        assert_eq!(reachable, vec![0, 1, 3, 4, 5]);
+
+        let state = State::from_instructions(program("jnz 0 5\ninc a\njnz 1 a\ninc b"));
+        assert_eq!(state.reachable_instructions().len(), 4);
+    }
+
+    #[test]
+    fn test_control_flow_graph() {
+        let state = State::from_instructions(program("cpy 2 a\ndec a\njnz a -1\njnz 1 c\njnz 0 5"));
+        assert_eq!(
+            state.control_flow_graph(),
+            vec![
+                (0, vec![Successor::Instruction(1)]),
+                (1, vec![Successor::Instruction(2)]),
+                (
+                    2,
+                    vec![Successor::Instruction(1), Successor::Instruction(3)]
+                ),
+                (3, vec![Successor::Dynamic]),
+                (4, vec![Successor::Halt]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_channels() {
+        let instructions = program("cpy 3 a\nout a\nout 7 ch1\ndec a\njnz a -3\nout 0 ch2");
+        assert_eq!(instructions[2].to_string(), "out 7 ch1");
+
+        let mut state = State::from_instructions(instructions);
+        let mut outputs = OutputChannels::new();
+        while !state.is_halted() {
+            state.step_recording(&mut outputs);
+        }
+
+        assert_eq!(outputs.get(DEFAULT_CHANNEL), &[3, 2, 1]);
+        assert_eq!(outputs.get("ch1"), &[7, 7, 7]);
+        assert_eq!(outputs.get("ch2"), &[0]);
+        assert_eq!(outputs.get("ch3"), &[] as &[i64]);
+    }
+
+    #[test]
+    fn test_run_to_halt_counting() {
+        // day 12 example
+        let mut state =
+            State::from_instructions(program("cpy 41 a\ninc a\ninc a\ndec a\njnz a 2\ndec a"));
+        let (steps, counts) = state.run_to_halt_counting();
+
+        assert_eq!(state["a"], 42);
+        assert_eq!(steps, 5);
+        let counts: Vec<(&str, usize)> = counts.into_iter().collect();
+        assert_eq!(counts, vec![("cpy", 1), ("dec", 1), ("inc", 2), ("jnz", 1)]);
+
+        let mut state = State::from_instructions(program("tgl 1\ninc a"));
+        let (_, counts) = state.run_to_halt_counting();
+        assert_eq!(counts.get("dec"), Some(&1));
+        assert_eq!(counts.get("inc"), None);
+    }
+
+    #[test]
+    fn test_run_counting_cycles() {
+        let instructions = program("cpy 2 a\ntgl a\ntgl a\ntgl a\ncpy 1 a\nout a\ndec a\ndec a");
+
+        let mut state = State::from_instructions(instructions.clone());
+        let mut expected = 0;
+        while let Some(inst) = state.current_instruction() {
+            expected += u64::from(inst.cost());
+            state.step();
+        }
+
+        let mut state = State::from_instructions(instructions);
+        assert_eq!(state.run_counting_cycles(), expected);
+        // cpy, two tgl, the toggled inc and the toggled jnz, which jumps to the last dec
+        assert_eq!(expected, 1 + 4 + 4 + 1 + 1 + 1);
+    }
+
+    #[test]
+    fn test_from_slice() {
+        // day 23 example
+        let original = program("cpy 2 a\ntgl a\ntgl a\ntgl a\ncpy 1 a\ndec a\ndec a");
+        let mut state = State::from_slice(&original);
+        assert_eq!(state, State::from_instructions(original.clone()));
+
+        while !state.is_halted() {
+            state.step();
+        }
+
+        assert_eq!(state["a"], 3);
+        assert_ne!(state.instructions(), &original[..]);
+        assert_eq!(original[3].to_string(), "tgl a");
+        assert_eq!(original[4].to_string(), "cpy 1 a");
+    }
+
+    #[test]
+    fn test_find_cycle() {
+        // toggles the `inc` back and forth, so the program only repeats every other pass
+        let state = State::from_instructions(program("tgl 2\njnz 1 -1\ninc a"));
+        assert_eq!(state.find_cycle(100), Some((0, 4)));
+        assert_eq!(state.find_cycle(4), None);
+
+        let state = State::from_instructions(program("cpy 3 a\ndec a\njnz a 2\njnz 1 -2"));
+        assert_eq!(state.find_cycle(100), None);
+
+        let state = State::from_instructions(program("inc a\njnz 1 -1"));
+        assert_eq!(state.find_cycle(100), None);
+    }
+
+    #[test]
+    fn test_into_instructions() {
+        // day 23 example
+        let original = program("cpy 2 a\ntgl a\ntgl a\ntgl a\ncpy 1 a\ndec a\ndec a");
+        let mut state = State::from_instructions(original.clone());
+        while !state.is_halted() {
+            state.step();
+        }
+
+        assert_eq!(state.instructions()[..3], original[..3]);
+        assert_eq!(state.instructions()[3].to_string(), "inc a");
+
+        let toggled = state.into_instructions();
+        assert_eq!(toggled[4].to_string(), "jnz 1 a");
+        assert_eq!(toggled[5..], original[5..]);
+    }
+
+    #[test]
+    fn test_patch_instruction() {
+        let instructions = program("cpy 3 a\ninc b\ndec a\njnz a -2");
+        let mut state = State::from_slice(&instructions);
+        state
+            .patch_instruction(1, "inc c".parse().unwrap())
+            .unwrap();
+        while !state.is_halted() {
+            state.step();
+        }
+        assert_eq!((state["b"], state["c"]), (0, 3));
+
+        assert!(matches!(
+            state.patch_instruction(4, "inc a".parse().unwrap()),
+            Err(AsmError::InstructionOutOfRange { index: 4, len: 4 })
+        ));
+        assert_eq!(state.instructions()[1].to_string(), "inc c");
+    }
+
+    #[test]
+    fn test_load_program() {
+        let mut state = State::from_instructions(program("cpy 3 a\ninc b"));
+        while !state.is_halted() {
+            state.step();
+        }
+
+        state.load_program(program("inc a\ndec b\ncpy a c"));
+        assert_eq!(state.ic, 0);
+        while !state.is_halted() {
+            state.step();
+        }
+
+        assert_eq!((state["a"], state["b"], state["c"]), (4, 0, 4));
+    }
+
+    #[test]
+    fn test_current_instruction() {
+        let mut state = State::from_instructions(program("inc a\ndec b"));
+        assert_eq!(state.current_instruction(), Some("inc a".parse().unwrap()));
+
+        state.step();
+        assert_eq!(state.current_instruction(), Some("dec b".parse().unwrap()));
+
+        state.step();
+        assert_eq!(state.current_instruction(), None);
+    }
+
+    #[test]
+    fn test_index_registers() {
+        let mut state = State::from_instructions(program("inc a"));
+        assert_eq!(state["z"], 0);
+
+        state["c"] = 5;
+        assert_eq!(state.registers[2], 5);
+
+        state.step();
+        assert_eq!(state["a"], 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_invalid_register() {
+        let state = State::from_instructions(program("inc a"));
+        let _ = state["foo"];
+    }
+
+    #[test]
+    fn test_validate() {
+        let state = State::from_instructions(program("cpy 1 2\ninc a\njnz a 0\ntgl 5\njnz a -5"));
+        assert_eq!(
+            state.validate(),
+            Err(vec![
+                AsmWarning::WriteToConstant { index: 0 },
+                AsmWarning::JumpToSelf { index: 2 },
+                AsmWarning::TargetOutOfProgram {
+                    index: 3,
+                    target: 8
+                },
+                AsmWarning::TargetOutOfProgram {
+                    index: 4,
+                    target: -1
+                },
+            ])
+        );
+
+        let state = State::from_instructions(program(DAY23_INPUT));
+        assert_eq!(state.validate(), Ok(()));
+
+        let state = State::from_instructions(program("cpy 41 a\ninc a\ndec b\njnz b -2\ntgl c"));
+        assert_eq!(state.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut state = State::from_instructions(program("tgl 1\njnz 1 2\ntgl 10"));
+
+        assert_eq!(
+            state.step(),
+            StepResult::OkToggle {
+                index: 1,
+                result: ToggleResult::Toggled {
+                    from: "jnz 1 2".parse().unwrap(),
+                    to: "cpy 1 2".parse().unwrap(),
+                }
+            }
+        );
+
+        // the toggled cpy writes into a constant and is skipped
+        assert_eq!(state.ignored_writes, 0);
+        assert_eq!(state.step(), StepResult::OkNoOutput);
+        assert_eq!(state.ic, 2);
+        assert_eq!(state.ignored_writes, 1);
+
+        assert_eq!(
+            state.step(),
+            StepResult::OkToggle {
+                index: 12,
+                result: ToggleResult::OutOfBounds
+            }
+        );
+        assert_eq!(state.step(), StepResult::OutOfProgram);
+    }
+
+    #[test]
+    fn test_ignored_writes_loop() {
+        // every pass writes to a constant, but the execution state still repeats
+        let mut state = State::from_instructions(program("out a\ninc a\ncpy 1 2\ndec a\njnz 1 -4"));
+        let mut seen = std::collections::HashSet::new();
+        let mut steps = 0;
+        while seen.insert(state.clone()) {
+            state.step();
+            steps += 1;
+            assert!(steps < 100, "no repeated state found");
+        }
+
+        assert_eq!(steps, 5);
+        assert_eq!(state.ignored_writes, 1);
+        assert_eq!(state, State::from_instructions(state.instructions.clone()));
+    }
+
+    #[test]
+    fn test_toggle_out_of_bounds() {
+        let mut state = State::from_instructions(program(
+            "tgl -1\ncpy -5 a\ntgl a\ncpy 9223372036854775807 a\ntgl a",
+        ));
+        let unchanged = state.instructions.clone();
+
+        while !state.is_halted() {
+            if let StepResult::OkToggle { result, .. } = state.step() {
+                assert_eq!(result, ToggleResult::OutOfBounds);
+            }
+        }
+
+        assert_eq!(state.instructions, unchanged);
+        assert_eq!(state.ic, 5);
+    }
+
+    #[test]
+    fn test_step_kind() {
+        let mut state = State::from_instructions(program("cpy 1 a\njnz a 2\ninc b\nout a\ntgl 5"));
+
+        assert_eq!(state.step_kind(), StepKind::Advanced);
+        assert_eq!(state.step_kind(), StepKind::Jumped);
+        assert_eq!(state.step_kind(), StepKind::Output(1));
+        assert_eq!(state.step_kind(), StepKind::Toggled);
+        assert_eq!(state.step_kind(), StepKind::Halted);
+        assert_eq!(state.registers[1], 0);
+    }
+
+    #[test]
+    fn test_step_with_events() {
+        // day 23 example
+        let mut state = State::from_instructions(program(
+            "cpy 2 a\ntgl a\ntgl a\ntgl a\ncpy 1 a\ndec a\ndec a",
+        ));
+
+        let mut events = Vec::new();
+        while state.step_with_events(|e| events.push(e)) != StepResult::OutOfProgram {}
+
+        assert_eq!(state.registers[0], 3);
+        assert_eq!(
+            events,
+            vec![
+                StepEvent::InstructionToggled {
+                    index: 3,
+                    from: "tgl a".parse().unwrap(),
+                    to: "inc a".parse().unwrap(),
+                },
+                StepEvent::InstructionToggled {
+                    index: 4,
+                    from: "cpy 1 a".parse().unwrap(),
+                    to: "jnz 1 a".parse().unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_run_logging() {
+        let mut state = State::from_instructions(program("cpy 2 a\ndec a\njnz a -1\nout a"));
+        let mut log: Vec<u8> = Vec::new();
+        state.run_logging(&mut log).unwrap();
+
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "0 cpy a=2\n1 dec a=1\n2 jnz\n1 dec a=0\n2 jnz\n3 out\n"
+        );
+    }
+
+    const DAY23_INPUT: &str = "cpy a b\ndec b\ncpy a d\ncpy 0 a\ncpy b c\ninc a\ndec c\njnz c -2\n\
+        dec d\njnz d -5\ndec b\ncpy b c\ncpy c d\ndec d\ninc c\njnz d -2\ntgl c\ncpy -16 c\n\
+        jnz 1 c\ncpy 89 c\njnz 79 d\ninc a\ninc d\njnz d -2\ninc c\njnz c -5";
+
+    #[test]
+    fn test_random_programs() {
+        assert_eq!(random_program(20, 1), random_program(20, 1));
+        assert_ne!(random_program(20, 1), random_program(20, 2));
+
+        for seed in 0..200 {
+            let instructions = random_program(1 + seed as usize % 30, seed);
+            for inst in &instructions {
+                assert_eq!(&inst.to_string().parse::<Instruction>().unwrap(), inst);
+            }
+
+            let mut state = State::from_instructions(instructions);
+            for _ in 0..1000 {
+                if state.step() == StepResult::OutOfProgram {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_countdown_patch() {
+        let instructions = program("cpy 1000 b\ndec b\njnz b -1\ninc a");
+        let mut plain = State::from_instructions(instructions.clone());
+        let mut accelerated = State::from_instructions(instructions);
+
+        let patches: Vec<SpeedPatch> = vec![Box::new(countdown_patch)];
+        assert!(plain.verify_optimization(&patches));
+
+        let mut plain_steps = 0;
+        while !plain.is_halted() {
+            plain.step();
+            plain_steps += 1;
+        }
+        let mut accelerated_steps = 0;
+        while !accelerated.is_halted() {
+            accelerated.step_accelerated();
+            accelerated_steps += 1;
+        }
+
+        assert_eq!(plain.registers, accelerated.registers);
+        assert_eq!(accelerated.registers[..2], [1, 0]);
+        assert_eq!(plain_steps, 2002);
+        assert_eq!(accelerated_steps, 3);
+    }
+
+    #[test]
+    fn test_multiply_patch() {
+        let mut state = State::from_instructions(program(DAY23_INPUT));
+        state.registers[0] = 7;
+
+        let patches: Vec<SpeedPatch> = vec![Box::new(multiply_patch)];
+        assert!(state.verify_optimization(&patches));
+
+        while !state.is_halted() {
+            state.step_turbo(multiply_patch);
+        }
+        assert_eq!(state.registers[0], 5040 + 89 * 79);
+
+        // a patch that computes the wrong result is caught
+        let mut state =
+            State::from_instructions(program("cpy 3 c\ninc a\ndec c\njnz c -2\ndec d\njnz d -5"));
+        state.registers[3] = 2;
+        let broken: Vec<SpeedPatch> = vec![Box::new(|s: &mut State| {
+            if s.ic == 1 {
+                s.registers[0] = 1;
+                s.registers[2] = 0;
+                s.registers[3] = 0;
+                s.ic = 6;
+                return Some(StepResult::OkNoOutput);
+            }
+            None
+        })];
+        assert!(state.verify_optimization(&patches));
+        assert!(!state.verify_optimization(&broken));
+    }
+
+    #[test]
+    fn test_multiply_patch_overflow() {
+        let mut state =
+            State::from_instructions(program("cpy b c\ninc a\ndec c\njnz c -2\ndec d\njnz d -5"));
+        state.registers[1] = i64::MAX;
+        state.registers[3] = 3;
+        state.step();
+
+        let before = state.clone();
+        assert!(multiply_patch(&mut state).is_none());
+        assert_eq!(state, before);
+
+        // without overflow, the same loop is patched
+        state.registers[1] = 4;
+        state.registers[2] = 4;
+        state.registers[3] = 3;
+        assert!(multiply_patch(&mut state).is_some());
+        assert_eq!(state.registers[0], 12);
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut state = State::from_instructions(program("inc a\ninc a\ntgl 1\ninc b"));
+        state.step();
+
+        let snapshot = state.snapshot();
+        state.step();
+        state.step();
+        state.step();
+        assert_ne!(state.registers, snapshot.registers);
+
+        state.restore(&snapshot);
+        assert_eq!(state.ic, 1);
+        assert_eq!(state.registers, snapshot.registers);
+        assert_eq!(state.instructions, snapshot.instructions);
+        assert_eq!(state.registers[0], 1);
+    }
+
+    #[test]
+    fn test_iter_steps() {
+        let mut state = State::from_instructions(program("cpy 2 a\ndec a\njnz a -1\ninc b"));
+        let trace: Vec<(i64, String)> = state
+            .iter_steps()
+            .map(|(ic, inst)| (ic, inst.to_string()))
+            .collect();
+
+        let expected: Vec<(i64, String)> = vec![
+            (0, "cpy 2 a"),
+            (1, "dec a"),
+            (2, "jnz a -1"),
+            (1, "dec a"),
+            (2, "jnz a -1"),
+            (3, "inc b"),
+        ]
+        .into_iter()
+        .map(|(ic, inst)| (ic, inst.to_string()))
+        .collect();
+
+        assert_eq!(trace, expected);
+        assert_eq!(state.registers[1], 1);
+        assert_eq!(state.iter_steps().count(), 0);
+    }
+
+    #[test]
+    fn test_step_turbo_multi() {
+        let patches: Vec<SpeedPatch> = vec![
+            Box::new(|s: &mut State| {
+                if s.ic == 5 {
+                    s.registers[0] = 100;
+                    s.ic += 1;
+                    return Some(StepResult::OkNoOutput);
+                }
+                None
+            }),
+            Box::new(|s: &mut State| {
+                if s.ic == 0 {
+                    s.registers[1] = 42;
+                    s.ic += 1;
+                    return Some(StepResult::OkNoOutput);
+                }
+                None
+            }),
+        ];
+
+        let mut state = State::from_instructions(program("inc a\ninc a"));
+        while state.step_turbo_multi(&patches) != StepResult::OutOfProgram {}
+
+        assert_eq!(state.registers[0], 1);
+        assert_eq!(state.registers[1], 42);
+    }
+
+    #[test]
+    fn test_annotate() {
+        // start of the day 23 puzzle input
+        let state = State::from_instructions(program(
+            "cpy a b\ndec b\ncpy a d\ncpy 0 a\ncpy b c\ninc a\ndec c\njnz c -2\ndec d\njnz d -5\n\
+             dec b\ncpy b c\ncpy c d\ndec d\ninc c\njnz d -2\ntgl 20\ncpy 1 2",
+        ));
+
+        let listing = state.annotate();
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines.len(), 18);
+        assert_eq!(lines[0], "  0: cpy a b");
+        assert_eq!(lines[5], "  5: inc a      ; multiply: a += c*d");
+        assert_eq!(lines[13], " 13: dec d      ; add: c += d");
+        assert!(lines[16].ends_with("; no-op: toggles outside of program"));
+        assert!(lines[17].ends_with("; no-op: writes to a constant"));
+        assert_eq!(lines.iter().filter(|l| l.contains(';')).count(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let instructions = program("cpy 41 a\ninc a\ninc a\ndec a\njnz a 2\ndec a");
+
+        let mut reference = State::from_instructions(instructions.clone());
+        while let StepResult::OkNoOutput = reference.step() {}
+
+        let mut state = State::from_instructions(instructions);
+        state.step();
+        state.step();
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"cpy 41 a\""));
+
+        let mut restored: State = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, state);
+
+        while let StepResult::OkNoOutput = restored.step() {}
+        assert_eq!(restored.registers, reference.registers);
+        assert_eq!(restored.registers[0], 42);
+    }
+}
@@ -98,7 +98,7 @@ impl std::str::FromStr for Instruction {
 }
 
 fn main() -> Result<()> {
-    let instructions: Vec<Instruction> = std::fs::read_to_string("data/day01/input")
+    let instructions: Vec<Instruction> = aoc2016::read_input(1)
         .context(Io)?
         .trim()
         .split(", ")
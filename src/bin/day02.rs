@@ -1,15 +1,15 @@
 use snafu::{ResultExt, Snafu};
 
-use aoc2016::map::{Map, MapError, ParseMapTile};
+use aoc2016::map::{Map, MapError, MapTile, ParseMapTile};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Tile {
     c: char,
 }
 
-impl std::fmt::Display for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.c)
+impl MapTile for Tile {
+    fn to_char(&self) -> char {
+        self.c
     }
 }
 
@@ -97,7 +97,7 @@ fn follow_instructions(
 }
 
 fn main() -> Result<()> {
-    let instructions: Vec<Vec<Direction>> = std::fs::read_to_string("data/day02/input")
+    let instructions: Vec<Vec<Direction>> = aoc2016::read_input(2)
         .context(Io)?
         .trim()
         .lines()
@@ -4,8 +4,8 @@ type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, Snafu)]
 enum Error {
-    #[snafu(display("I/O error: {}", source))]
-    Io { source: std::io::Error },
+    #[snafu(display("Input error: {}", source))]
+    Input { source: aoc2016::input::InputError },
 
     #[snafu(display("Int format error for '{}': {}", data, source))]
     ParseInt {
@@ -14,6 +14,23 @@ enum Error {
     },
 }
 
+struct Row(Vec<usize>);
+
+impl std::str::FromStr for Row {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split_whitespace()
+            .map(|t| {
+                t.parse::<usize>().context(ParseInt {
+                    data: t.to_string(),
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Row)
+    }
+}
+
 struct Triangle {
     a: usize,
     b: usize,
@@ -27,19 +44,11 @@ impl Triangle {
 }
 
 fn main() -> Result<()> {
-    let matrix: Vec<Vec<usize>> = std::fs::read_to_string("data/day03/input")
-        .context(Io)?
-        .lines()
-        .map(|l| {
-            l.split_whitespace()
-                .map(|t| {
-                    t.parse::<usize>().context(ParseInt {
-                        data: t.to_string(),
-                    })
-                })
-                .collect::<Result<_>>()
-        })
-        .collect::<Result<_>>()?;
+    let matrix: Vec<Vec<usize>> = aoc2016::read_lines_parsed::<Row>(3)
+        .context(Input)?
+        .into_iter()
+        .map(|r| r.0)
+        .collect();
 
     let triangles1: Vec<Triangle> = matrix
         .iter()
@@ -99,7 +99,7 @@ enum Error {
 }
 
 fn main() -> Result<()> {
-    let rooms: Vec<Room> = std::fs::read_to_string("data/day04/input")
+    let rooms: Vec<Room> = aoc2016::read_input(4)
         .context(Io)?
         .lines()
         .map(|l| l.parse())
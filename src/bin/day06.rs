@@ -17,7 +17,7 @@ enum Error {
 }
 
 fn main() -> Result<()> {
-    let data: Vec<Vec<char>> = std::fs::read_to_string("data/day06/input")
+    let data: Vec<Vec<char>> = aoc2016::read_input(6)
         .context(Io)?
         .lines()
         .map(|l| l.chars().collect())
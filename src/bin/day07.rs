@@ -121,7 +121,7 @@ impl Address {
 }
 
 fn main() -> Result<()> {
-    let addresses: Vec<Address> = std::fs::read_to_string("data/day07/input")
+    let addresses: Vec<Address> = aoc2016::read_input(7)
         .context(Io)?
         .trim()
         .lines()
@@ -1,6 +1,6 @@
 use snafu::{ResultExt, Snafu};
 
-use aoc2016::map::Map;
+use aoc2016::map::{Map, MapTile};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -22,9 +22,9 @@ enum Error {
 #[derive(Debug, Clone)]
 struct Tile;
 
-impl std::fmt::Display for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "#")
+impl MapTile for Tile {
+    fn to_char(&self) -> char {
+        '#'
     }
 }
 
@@ -158,7 +158,7 @@ impl Instruction {
 }
 
 fn main() -> Result<()> {
-    let instructions: Vec<Instruction> = std::fs::read_to_string("data/day08/input")
+    let instructions: Vec<Instruction> = aoc2016::read_input(8)
         .context(Io)?
         .lines()
         .map(|l| l.parse())
@@ -63,10 +63,7 @@ fn get_decompressed_length(s: &str, expand_recursive: bool) -> Result<usize> {
 }
 
 fn main() -> Result<()> {
-    let input: String = std::fs::read_to_string("data/day09/input")
-        .context(Io)?
-        .trim()
-        .to_string();
+    let input: String = aoc2016::read_input(9).context(Io)?.trim().to_string();
 
     println!(
         "Part 1: {} characters",
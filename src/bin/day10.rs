@@ -203,7 +203,7 @@ enum Error {
 }
 
 fn main() -> Result<()> {
-    let instructions: Vec<Instruction> = std::fs::read_to_string("data/day10/input")
+    let instructions: Vec<Instruction> = aoc2016::read_input(10)
         .context(Io)?
         .lines()
         .map(|l| l.parse())
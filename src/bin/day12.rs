@@ -1,6 +1,6 @@
 use snafu::{ResultExt, Snafu};
 
-use aoc2016::asmbunny::{AsmError, Instruction, State};
+use aoc2016::asmbunny::{parse_program, run_with_seed, AsmError, Instruction};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -14,36 +14,45 @@ enum Error {
 }
 
 fn main() -> Result<()> {
-    let instructions: Vec<Instruction> = std::fs::read_to_string("data/day12/input")
-        .context(Io)?
-        .lines()
-        .map(|l| l.parse().context(Asm))
-        .collect::<Result<_>>()?;
-
-    let mut state = State::from_instructions(instructions.clone());
-    while (state.ic >= 0) && (state.ic < instructions.len() as i64) {
-        state.step();
-    }
-
-    println!("Part 1: {:#?}", state.registers[0]);
-
-    let mut state: State = State::from_instructions(instructions.clone());
-    state.registers[2] = 1;
-    while (state.ic >= 0) && (state.ic < instructions.len() as i64) {
-        state.step();
-    }
-
-    println!("Part 2: {:#?}", state.registers[0]);
-
+    let instructions: Vec<Instruction> =
+        parse_program(&aoc2016::read_input(12).context(Io)?).context(Asm)?;
+
+    println!("Part 1: {:#?}", run_with_seed(&instructions, &[], false));
+    println!(
+        "Part 2: {:#?}",
+        run_with_seed(&instructions, &[("c", 1)], false)
+    );
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aoc2016::asmbunny::State;
+
+    /// The fibonacci loop at the heart of the day 12 input
+    const FIB: &str =
+        "cpy 1 a\ncpy 1 b\ncpy 20 d\ncpy a c\ninc a\ndec b\njnz b -2\ncpy c b\ndec d\njnz d -6";
 
     #[test]
-    fn it_works() -> Result<()> {
+    fn test_fibonacci() -> Result<()> {
+        let mut state = State::from_instructions(parse_program(FIB).context(Asm)?);
+        let mut steps = 0;
+        while !state.is_halted() {
+            state.step();
+            steps += 1;
+        }
+
+        let (mut a, mut b) = (1, 1);
+        for _ in 0..20 {
+            let c = a;
+            a += b;
+            b = c;
+        }
+
+        assert_eq!(state["a"], a);
+        assert_eq!(state["b"], b);
+        assert!(steps > 10_000);
         Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use aoc2016::map::Map;
+use aoc2016::map::{Map, MapTile};
 use snafu::{ResultExt, Snafu};
 
 type Result<T> = std::result::Result<T, Error>;
@@ -57,17 +57,13 @@ enum Tile {
     Blocked,
 }
 
-impl std::fmt::Display for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Tile::Visited => "O",
-                Tile::Free => ".",
-                Tile::Blocked => "#",
-            }
-        )
+impl MapTile for Tile {
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Visited => 'O',
+            Tile::Free => '.',
+            Tile::Blocked => '#',
+        }
     }
 }
 
@@ -120,7 +120,7 @@ fn solve(discs: &[Disc]) -> Option<i64> {
 }
 
 fn main() -> Result<()> {
-    let discs1: Vec<Disc> = std::fs::read_to_string("data/day15/input")
+    let discs1: Vec<Disc> = aoc2016::read_input(15)
         .context(Io)?
         .lines()
         .map(|l| l.parse())
@@ -1,4 +1,6 @@
+use aoc2016::map::{simulate, Map, MapTile};
 use snafu::{ResultExt, Snafu};
+use std::collections::HashMap;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -14,14 +16,18 @@ enum Error {
     },
 }
 
-fn generate(start_row: &[bool], rows: usize) -> usize {
-    let width = start_row.len();
-    let mut last_row = start_row.to_vec();
-    let mut new_row = Vec::with_capacity(width);
-    let mut n_safe = start_row.iter().filter(|v| !*v).count();
+/// Check whether a tile becomes a trap given the three tiles above it
+fn is_trap(left: bool, center: bool, right: bool) -> bool {
+    matches!(
+        (left, center, right),
+        (true, true, false) | (false, true, true) | (true, false, false) | (false, false, true)
+    )
+}
 
-    for _ in 1..rows {
-        for j in 0..width {
+fn next_row(last_row: &[bool]) -> Vec<bool> {
+    let width = last_row.len();
+    (0..width)
+        .map(|j| {
             let left = if j > 0 { last_row[j - 1] } else { false };
             let center = last_row[j];
             let right = if j < width - 1 {
@@ -30,30 +36,111 @@ fn generate(start_row: &[bool], rows: usize) -> usize {
                 false
             };
 
-            let current = match (left, center, right) {
-                (true, true, false) => true,
-                (false, true, true) => true,
-                (true, false, false) => true,
-                (false, false, true) => true,
-                _ => false,
-            };
+            is_trap(left, center, right)
+        })
+        .collect()
+}
+
+fn count_safe(row: &[bool]) -> usize {
+    row.iter().filter(|v| !*v).count()
+}
+
+/// Lazily generate the rows of the automaton, starting with `start`
+fn rows_iter(start: Vec<bool>) -> impl Iterator<Item = Vec<bool>> {
+    std::iter::successors(Some(start), |row| Some(next_row(row)))
+}
+
+fn generate(start_row: &[bool], rows: usize) -> usize {
+    rows_iter(start_row.to_vec())
+        .take(rows)
+        .map(|row| count_safe(&row))
+        .sum()
+}
 
-            if !current {
-                n_safe += 1;
-            }
+/// Count safe tiles like `generate`, but look for a repeating row within the first
+/// `max_search` rows. If one is found, the count is extrapolated over the full cycles and
+/// the cycle length is returned as well. Otherwise, the rows are generated directly.
+fn generate_cyclic(start_row: &[bool], rows: usize, max_search: usize) -> (usize, Option<usize>) {
+    let mut seen: HashMap<Vec<bool>, usize> = HashMap::new();
+    let mut safe_counts: Vec<usize> = Vec::new();
+    let mut row = start_row.to_vec();
+
+    for i in 0..rows.min(max_search) {
+        if let Some(&first) = seen.get(&row) {
+            let cycle_len = i - first;
+            let prefix: usize = safe_counts[..first].iter().sum();
+            let cycle: usize = safe_counts[first..].iter().sum();
+
+            let remaining = rows - first;
+            let remainder: usize = safe_counts[first..first + remaining % cycle_len]
+                .iter()
+                .sum();
+
+            return (
+                prefix + (remaining / cycle_len) * cycle + remainder,
+                Some(cycle_len),
+            );
+        }
+
+        safe_counts.push(count_safe(&row));
+        let next = next_row(&row);
+        seen.insert(row, i);
+        row = next;
+    }
+
+    let mut n_safe: usize = safe_counts.iter().sum();
+    if safe_counts.len() < rows {
+        n_safe += generate(&row, rows - safe_counts.len());
+    }
+    (n_safe, None)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Safe,
+    Trap,
+}
 
-            new_row.push(current);
+impl MapTile for Tile {
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Safe => '.',
+            Tile::Trap => '^',
         }
+    }
+}
 
-        last_row = new_row;
-        new_row = Vec::with_capacity(width);
+/// Build the full grid of `rows` rows using the generic `simulate` engine. Each generation
+/// recomputes every row from the row above it, so the grid is final after `rows`
+/// generations. This is much slower than `generate` and only meant to check it.
+fn generate_map(start_row: &[bool], rows: usize) -> Map<[i32; 2], Tile> {
+    let mut map: Map<[i32; 2], Tile> = Map::new();
+    for i in 0..rows as i32 {
+        map.push_row(
+            i,
+            start_row.iter().map(|&trap| match (i, trap) {
+                (0, true) => Tile::Trap,
+                _ => Tile::Safe,
+            }),
+        );
     }
 
-    n_safe
+    simulate(map, rows, |map, [i, j]| {
+        if i == 0 {
+            return *map.get(&[i, j]).unwrap();
+        }
+
+        let trap = |j: i32| map.get(&[i - 1, j]) == Some(&Tile::Trap);
+        if is_trap(trap(j - 1), trap(j), trap(j + 1)) {
+            Tile::Trap
+        } else {
+            Tile::Safe
+        }
+    })
 }
 
 fn main() -> Result<()> {
-    let first_row: Vec<bool> = std::fs::read_to_string("data/day18/input")
+    let first_row: Vec<bool> = aoc2016::read_input(18)
         .context(Io)?
         .trim()
         .chars()
@@ -61,7 +148,16 @@ fn main() -> Result<()> {
         .collect();
 
     println!("Part 1: Got {} safe tiles", generate(&first_row, 40));
-    println!("Part 2: Got {} safe tiles", generate(&first_row, 400000));
+    if std::env::args().any(|a| a == "--verify") {
+        let n_safe = generate_map(&first_row, 40).count_where(|_, t| *t == Tile::Safe);
+        println!("Part 1 (map): Got {} safe tiles", n_safe);
+    }
+    let (n_safe, cycle_len) = generate_cyclic(&first_row, 400000, 10000);
+    match cycle_len {
+        Some(l) => println!("Found a cycle of {} rows", l),
+        None => println!("No cycle found, generated all rows"),
+    }
+    println!("Part 2: Got {} safe tiles", n_safe);
 
     Ok(())
 }
@@ -70,8 +166,51 @@ fn main() -> Result<()> {
 mod tests {
     use super::*;
 
+    fn parse_row(s: &str) -> Vec<bool> {
+        s.chars().map(|c| c == '^').collect()
+    }
+
+    #[test]
+    fn test_generate() {
+        assert_eq!(generate(&parse_row("..^^."), 3), 6);
+        assert_eq!(generate(&parse_row(".^^.^.^^^^"), 10), 38);
+    }
+
+    #[test]
+    fn test_rows_iter() {
+        let rows: Vec<Vec<bool>> = rows_iter(parse_row("..^^.")).take(3).collect();
+        assert_eq!(
+            rows,
+            vec![parse_row("..^^."), parse_row(".^^^^"), parse_row("^^..^")]
+        );
+    }
+
     #[test]
-    fn it_works() -> Result<()> {
-        Ok(())
+    fn test_generate_map() {
+        let row = parse_row(".^^.^.^^^^");
+        let map = generate_map(&row, 10);
+
+        assert_eq!(map.get_extent(), ([0, 0], [9, 9]));
+        assert_eq!(format!("{}", map).lines().nth(9), Some("^^.^^^..^^"));
+        for rows in 1..=30 {
+            let map = generate_map(&row, rows);
+            assert_eq!(
+                map.count_where(|_, t| *t == Tile::Safe),
+                generate(&row, rows)
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_cyclic() {
+        let row = parse_row(".^^.^.^^^^");
+
+        let (n_safe, cycle_len) = generate_cyclic(&row, 5000, 2000);
+        assert_eq!(cycle_len, Some(62));
+        assert_eq!(n_safe, generate(&row, 5000));
+
+        // without a cycle in the search window, rows are generated directly
+        assert_eq!(generate_cyclic(&row, 5000, 3), (generate(&row, 5000), None));
+        assert_eq!(generate_cyclic(&row, 10, 2000).0, 38);
     }
 }
@@ -4,8 +4,8 @@ type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, Snafu)]
 enum Error {
-    #[snafu(display("I/O error: {}", source))]
-    Io { source: std::io::Error },
+    #[snafu(display("Input error: {}", source))]
+    Input { source: aoc2016::input::InputError },
 
     #[snafu(display("Int format error for '{}': {}", data, source))]
     ParseInt {
@@ -45,11 +45,7 @@ impl std::str::FromStr for IPRange {
 }
 
 fn main() -> Result<()> {
-    let mut ranges: Vec<IPRange> = std::fs::read_to_string("data/day20/input")
-        .context(Io)?
-        .lines()
-        .map(|l| l.parse())
-        .collect::<Result<_>>()?;
+    let mut ranges: Vec<IPRange> = aoc2016::read_lines_parsed(20).context(Input)?;
 
     let max_value = 4294967295;
 
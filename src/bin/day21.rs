@@ -203,7 +203,7 @@ fn chars(s: &str) -> Vec<char> {
 }
 
 fn main() -> Result<()> {
-    let mut instructions: Vec<Instruction> = std::fs::read_to_string("data/day21/input")
+    let mut instructions: Vec<Instruction> = aoc2016::read_input(21)
         .context(Io)?
         .lines()
         .map(|l| l.parse())
@@ -1,14 +1,14 @@
-use std::{
-    cmp::Reverse,
-    collections::{BinaryHeap, HashMap, VecDeque},
-};
+use std::collections::HashMap;
 
 use snafu::{ResultExt, Snafu};
 
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use aoc2016::map::Map;
+use aoc2016::{
+    map::{Connectivity, Map, MapTile},
+    search::astar_path,
+};
 
 lazy_static! {
     static ref RE_NODE: Regex =
@@ -30,17 +30,27 @@ enum Error {
 
     #[snafu(display("Invalid node: '{}'", data))]
     ParseNode { data: String },
+
+    #[snafu(display("Missing value for argument '{}'", name))]
+    MissingArgument { name: String },
+
+    #[snafu(display("Aborted after reaching the step limit of {}", max_steps))]
+    StepLimit { max_steps: usize },
+
+    #[snafu(display("No solution found"))]
+    NoSolution,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Node {
-    x: i8,
-    y: i8,
+    x: i32,
+    y: i32,
     size: u16,
     used: u16,
     avail: u16,
     use_pct: u16,
     contains_goal: bool,
+    is_wall: bool,
 }
 
 impl std::str::FromStr for Node {
@@ -58,10 +68,10 @@ impl std::str::FromStr for Node {
         let avail = captures.get(5).unwrap().as_str();
         let use_pct = captures.get(6).unwrap().as_str();
 
-        let x: i8 = x.parse().context(ParseInt {
+        let x: i32 = x.parse().context(ParseInt {
             data: x.to_string(),
         })?;
-        let y: i8 = y.parse().context(ParseInt {
+        let y: i32 = y.parse().context(ParseInt {
             data: y.to_string(),
         })?;
         let size: u16 = size.parse().context(ParseInt {
@@ -85,199 +95,323 @@ impl std::str::FromStr for Node {
             avail,
             use_pct,
             contains_goal: false,
+            is_wall: false,
         })
     }
 }
 
 impl Node {
+    /// Check whether all data on this non-empty node fits into the available space of `target`
     fn can_send_to(&self, target: &Node) -> bool {
         self.used > 0 && target.avail >= self.used
     }
-}
 
-impl std::fmt::Display for Node {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let symbol = if self.used > 100 {
-            "#"
-        } else if self.used < self.size / 2 {
-            "."
-        } else {
-            "/"
-        };
+    /// Check whether this node and `other` form a viable pair: this node is not empty, the
+    /// nodes are at different positions and this node's data fits into `other`
+    fn is_viable_pair_with(&self, other: &Node) -> bool {
+        (self.x, self.y) != (other.x, other.y) && self.can_send_to(other)
+    }
+}
 
+impl MapTile for Node {
+    fn to_char(&self) -> char {
         if self.contains_goal {
-            write!(f, "!{}", symbol)
+            'G'
+        } else if self.is_wall {
+            '#'
         } else {
-            write!(f, " {}", symbol)
+            '.'
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Count all ordered pairs of distinct nodes where the first node's data fits into the second
+fn count_viable_pairs(nodes: &[Node]) -> usize {
+    nodes
+        .iter()
+        .map(|n| nodes.iter().filter(|m| n.is_viable_pair_with(m)).count())
+        .sum()
+}
+
+/// Search state for moving the goal data around. Since there is only a single empty node
+/// and all other movable nodes are too full to receive more data, every move shifts the
+/// empty "hole" into a neighboring node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct State {
-    steps: usize,
-    map: Map<[i8; 2], Node>,
+    hole: [i32; 2],
+    goal: [i32; 2],
 }
 
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+const OFFSETS: [[i32; 2]; 4] = [[-1, 0], [1, 0], [0, -1], [0, 1]];
+
+fn manhattan(a: &[i32; 2], b: &[i32; 2]) -> usize {
+    ((a[0] - b[0]).abs() + (a[1] - b[1]).abs()) as usize
 }
 
-impl Direction {
-    fn get_offset(&self) -> [i8; 2] {
-        match self {
-            Direction::Up => [-1, 0],
-            Direction::Down => [1, 0],
-            Direction::Left => [0, -1],
-            Direction::Right => [0, 1],
-        }
+/// Tag all nodes whose data can never be moved as walls. That is the case if the data
+/// exceeds the size of every neighbor, or if it does not fit into the empty node, which
+/// is the only node with enough room to receive data.
+fn tag_walls(map: &mut Map<[i32; 2], Node>, empty: &[i32; 2]) {
+    let capacity = map.get(empty).expect("empty node").size;
+    let walls = map.find_all_where(|pos, n| {
+        n.used > capacity
+            || map
+                .neighbors_with(*pos, Connectivity::Four.offsets())
+                .all(|(_, m)| n.used > m.size)
+    });
+
+    for pos in walls {
+        map.get_mut(&pos).unwrap().is_wall = true;
     }
 }
 
-impl State {
-    fn make_move(&self, pos_source: &[i8; 2], direction: &Direction) -> Option<Self> {
-        let offset = direction.get_offset();
-        let pos_target = [pos_source[0] + offset[0], pos_source[1] + offset[1]];
+/// Render the grid with `#` for walls, `_` for the empty node, `G` for the goal data and
+/// `.` for all other nodes
+fn render(map: &Map<[i32; 2], Node>, hole: [i32; 2]) -> String {
+    let mut markers = HashMap::new();
+    markers.insert(hole, '_');
+    map.display_with_markers(&markers)
+}
+
+/// Moving all data from one node (first) to a neighboring one (second), as `[y, x]` positions
+type Move = ([i32; 2], [i32; 2]);
+
+/// Arrange the nodes in a grid with the goal data at the top right and walls tagged.
+/// Also returns the position of the empty node.
+fn build_grid(nodes: &[Node]) -> (Map<[i32; 2], Node>, [i32; 2]) {
+    let mut map: Map<[i32; 2], Node> = Map::new();
+    map.extend(nodes.iter().map(|n| ([n.y, n.x], n.clone())));
 
-        let old_source = self.map.get(pos_source)?;
-        let old_target = self.map.get(&pos_target)?;
+    let (_, max) = map.get_extent();
+    let goal = [0, max[1]];
+    map.get_mut(&goal).unwrap().contains_goal = true;
+
+    let hole = map.find(|n| n.used == 0).expect("Found empty");
 
-        let sum = old_source.used + old_target.used;
-        if sum > old_target.size {
-            println!("Not enough space: {}", sum);
-            return None;
+    tag_walls(&mut map, &hole);
+    (map, hole)
+}
+
+/// Find the shortest sequence of moves that brings the goal data at the top right to the
+/// origin
+fn solve_part2(nodes: &[Node], max_steps: Option<usize>) -> Result<Vec<Move>> {
+    let (map, hole) = build_grid(nodes);
+    let (_, max) = map.get_extent();
+    let goal = [0, max[1]];
+
+    let mut expansions = 0;
+    let neighbors = |s: &State| {
+        expansions += 1;
+        if expansions % 100_000 == 0 {
+            println!("expanded {} states", expansions);
         }
 
-        let mut new_source = old_source.clone();
-        let mut new_target = old_target.clone();
+        OFFSETS
+            .iter()
+            .map(|ofs| [s.hole[0] + ofs[0], s.hole[1] + ofs[1]])
+            .filter(|pos| map.get(pos).map(|n| !n.is_wall).unwrap_or(false))
+            .map(|pos| {
+                let goal = if pos == s.goal { s.hole } else { s.goal };
+                (State { hole: pos, goal }, 1)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The hole has to reach the goal to move it for the first time. Any further step of
+    // the goal requires at least 3 moves: 2 to bring the hole around to another side of
+    // the goal and 1 to swap. This keeps the heuristic admissible so A* stays optimal.
+    let heuristic = |s: &State| {
+        let goal_dist = manhattan(&s.goal, &[0, 0]);
+        if goal_dist == 0 {
+            0
+        } else {
+            manhattan(&s.hole, &s.goal) + 3 * (goal_dist - 1)
+        }
+    };
+
+    let (_, path) = astar_path(
+        State { hole, goal },
+        neighbors,
+        heuristic,
+        |s| s.goal == [0, 0],
+        max_steps,
+    )
+    .ok_or(match max_steps {
+        Some(max_steps) => Error::StepLimit { max_steps },
+        None => Error::NoSolution,
+    })?;
+
+    // every step moves the data of the node that becomes the new hole into the old hole
+    Ok(path.windows(2).map(|w| (w[1].hole, w[0].hole)).collect())
+}
 
-        new_target.used = sum;
-        new_source.used = 0;
+/// Parse an optional `--max-steps N` command-line argument
+fn parse_max_steps(args: &[String]) -> Result<Option<usize>> {
+    let pos = match args.iter().position(|a| a == "--max-steps") {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
 
-        new_target.contains_goal = new_source.contains_goal;
-        new_source.contains_goal = false;
+    let value = args.get(pos + 1).ok_or(Error::MissingArgument {
+        name: "--max-steps".to_string(),
+    })?;
 
-        let mut new_state = self.clone();
-        new_state.steps += 1;
-        new_state.map.set(*pos_source, new_source);
-        new_state.map.set(pos_target, new_target);
+    let max_steps = value.parse().context(ParseInt {
+        data: value.to_string(),
+    })?;
 
-        Some(new_state)
-    }
+    Ok(Some(max_steps))
 }
 
 fn main() -> Result<()> {
-    let nodes: Vec<Node> = std::fs::read_to_string("data/day22/input")
+    let args: Vec<String> = std::env::args().collect();
+    let max_steps = parse_max_steps(&args)?;
+
+    let nodes: Vec<Node> = aoc2016::read_input(22)
         .context(Io)?
         .lines()
         .filter_map(|l| l.parse().ok())
         .collect();
 
-    let mut n_viable = 0;
-    for (i, n) in nodes.iter().enumerate() {
-        for (j, m) in nodes.iter().enumerate() {
-            if i != j && n.can_send_to(m) {
-                n_viable += 1;
-            }
-        }
+    println!("Part 1: got {} viable pairs", count_viable_pairs(&nodes));
+
+    let (map, hole) = build_grid(&nodes);
+    println!(
+        "{}\nhole @ {:?}, {} walls",
+        render(&map, hole),
+        hole,
+        map.count_where(|_, n| n.is_wall)
+    );
+
+    let moves = solve_part2(&nodes, max_steps)?;
+    for (from, to) in &moves {
+        println!(
+            "node-x{}-y{} -> node-x{}-y{}",
+            from[1], from[0], to[1], to[0]
+        );
     }
+    println!("Part 2: {} steps", moves.len());
 
-    println!("Part 1: got {} viable pairs", n_viable);
+    Ok(())
+}
 
-    // convert to useful representation
-    let mut map: Map<[i8; 2], Node> = Map::new();
-    for n in nodes {
-        map.set([n.y, n.x], n);
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let (_, max) = map.get_extent();
-    let mut target_pos = [0, max[1]];
-    map.get_mut(&target_pos).unwrap().contains_goal = true;
-
-    let mut empty_pos = None;
-    let mut empty_used = std::u16::MAX;
-    for (pos, n) in map.data.iter() {
-        if n.used < empty_used {
-            empty_pos = Some(pos);
-            empty_used = n.used;
-        }
+    #[test]
+    fn it_works() -> Result<()> {
+        Ok(())
     }
 
-    let mut empty_pos = *empty_pos.expect("Found empty");
-    let mut state = State { map, steps: 0 };
-
-    println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
-    for _ in 0..4 {
-        state = state
-            .make_move(&[empty_pos[0], empty_pos[1] - 1], &Direction::Right)
-            .expect("valid move");
-        empty_pos[1] -= 1;
-        println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
+    const SAMPLE: &str = "/dev/grid/node-x0-y0   10T    8T     2T   80%
+/dev/grid/node-x0-y1   11T    6T     5T   54%
+/dev/grid/node-x0-y2   32T   28T     4T   87%
+/dev/grid/node-x1-y0    9T    7T     2T   77%
+/dev/grid/node-x1-y1    8T    0T     8T    0%
+/dev/grid/node-x1-y2   11T    7T     4T   63%
+/dev/grid/node-x2-y0   10T    6T     4T   60%
+/dev/grid/node-x2-y1    9T    8T     1T   88%
+/dev/grid/node-x2-y2    9T    6T     3T   66%";
+
+    fn parse_sample() -> Result<Vec<Node>> {
+        SAMPLE.lines().map(|l| l.parse()).collect()
     }
 
-    while empty_pos[0] > 0 {
-        state = state
-            .make_move(&[empty_pos[0] - 1, empty_pos[1]], &Direction::Down)
-            .expect("valid move");
-        empty_pos[0] -= 1;
-        println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
+    #[test]
+    fn test_parse_wide_node() -> Result<()> {
+        let node: Node = "/dev/grid/node-x200-y3   90T   70T    20T   77%".parse()?;
+        assert_eq!((node.x, node.y), (200, 3));
+        assert_eq!((node.size, node.used, node.avail), (90, 70, 20));
+        Ok(())
     }
 
-    while empty_pos[1] < target_pos[1] - 1 {
-        state = state
-            .make_move(&[empty_pos[0], empty_pos[1] + 1], &Direction::Left)
-            .expect("valid move");
-        empty_pos[1] += 1;
-        println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
+    #[test]
+    fn test_count_viable_pairs() -> Result<()> {
+        let nodes = parse_sample()?;
+        assert_eq!(count_viable_pairs(&nodes), 7);
+
+        // a node whose data would fit into itself does not pair with itself
+        let node: Node = "/dev/grid/node-x0-y0   10T    3T     7T   30%".parse()?;
+        assert_eq!(count_viable_pairs(std::slice::from_ref(&node)), 0);
+
+        // empty nodes are never the source of a viable pair
+        let empty: Node = "/dev/grid/node-x1-y0   10T    0T    10T    0%".parse()?;
+        assert_eq!(count_viable_pairs(&[node, empty]), 1);
+
+        Ok(())
     }
 
-    while target_pos[1] > 0 {
-        state = state
-            .make_move(&target_pos, &Direction::Left)
-            .expect("valid move");
-        target_pos[1] -= 1;
-        empty_pos[1] += 1;
+    #[test]
+    fn test_is_viable_pair_with() -> Result<()> {
+        let a: Node = "/dev/grid/node-x0-y0   10T    3T     7T   30%".parse()?;
+        let b: Node = "/dev/grid/node-x1-y0   11T    8T     3T   72%".parse()?;
+        let empty: Node = "/dev/grid/node-x2-y0   10T    0T    10T    0%".parse()?;
 
-        println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
+        // a node never pairs with itself, even if its data would fit
+        assert!(!a.is_viable_pair_with(&a));
 
-        if target_pos[1] == 0 {
-            break;
-        }
+        // empty nodes are never the source of a pair
+        assert!(!empty.is_viable_pair_with(&a));
+        assert!(a.is_viable_pair_with(&empty));
 
-        state = state
-            .make_move(&[empty_pos[0] + 1, empty_pos[1]], &Direction::Up)
-            .expect("valid move");
-        empty_pos[0] += 1;
+        // 3T of data fit into exactly 3T of available space, 8T do not fit into 7T
+        assert!(a.is_viable_pair_with(&b));
+        assert!(!b.is_viable_pair_with(&a));
 
-        println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
+        Ok(())
+    }
 
-        for _ in 0..2 {
-            state = state
-                .make_move(&[empty_pos[0], empty_pos[1] - 1], &Direction::Right)
-                .expect("valid move");
-            empty_pos[1] -= 1;
-            println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
-        }
+    #[test]
+    fn test_tag_walls() -> Result<()> {
+        let mut map: Map<[i32; 2], Node> = Map::new();
+        map.extend(parse_sample()?.into_iter().map(|n| ([n.y, n.x], n)));
+        map.get_mut(&[0, 2]).unwrap().contains_goal = true;
+
+        tag_walls(&mut map, &[1, 1]);
+        assert_eq!(map.find_all_where(|_, n| n.is_wall), vec![[2, 0]]);
+        assert_eq!(render(&map, [1, 1]), "..G\n._.\n#..\n");
+
+        // the data of a node that exceeds every neighbor's size can never move, even if it
+        // would fit into the empty node
+        map.get_mut(&[1, 1]).unwrap().size = 40;
+        map.get_mut(&[2, 0]).unwrap().is_wall = false;
+        tag_walls(&mut map, &[1, 1]);
+        assert!(map.get(&[2, 0]).unwrap().is_wall);
 
-        state = state
-            .make_move(&[empty_pos[0] - 1, empty_pos[1]], &Direction::Down)
-            .expect("valid move");
-        empty_pos[0] -= 1;
-        println!("step {}:\n{}\n{:?}", state.steps, state.map, empty_pos);
+        Ok(())
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_sample_part2() -> Result<()> {
+        let nodes = parse_sample()?;
+        let moves = solve_part2(&nodes, None)?;
+        assert_eq!(moves.len(), 7);
+
+        // the first move fills the empty node, the last one brings the goal data home
+        assert_eq!(moves[0].1, [1, 1]);
+        assert_eq!(moves[6], ([0, 1], [0, 0]));
+        for (w, (from, to)) in moves.windows(2).zip(moves.iter()) {
+            assert_eq!(manhattan(from, to), 1);
+            assert_eq!(w[1].1, w[0].0);
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert!(solve_part2(&nodes, Some(6)).is_err());
+        Ok(())
+    }
 
     #[test]
-    fn it_works() -> Result<()> {
+    fn test_parse_max_steps() -> Result<()> {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(parse_max_steps(&args(&["day22"]))?, None);
+        assert_eq!(
+            parse_max_steps(&args(&["day22", "--max-steps", "50"]))?,
+            Some(50)
+        );
+        assert!(parse_max_steps(&args(&["day22", "--max-steps"])).is_err());
+        assert!(parse_max_steps(&args(&["day22", "--max-steps", "x"])).is_err());
+
         Ok(())
     }
 }
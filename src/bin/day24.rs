@@ -1,12 +1,12 @@
 use std::{
     cmp::Reverse,
-    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    collections::{BinaryHeap, HashMap, HashSet},
     fs::File,
 };
 
 use snafu::{ResultExt, Snafu};
 
-use aoc2016::map::{Map, MapError, ParseMapTile};
+use aoc2016::map::{Connectivity, Map, MapError, MapTile, ParseMapTile};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -46,39 +46,17 @@ impl ParseMapTile for Tile {
     }
 }
 
-impl std::fmt::Display for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl MapTile for Tile {
+    fn to_char(&self) -> char {
         match self {
-            Tile::Floor => write!(f, "."),
-            Tile::Wall => write!(f, "#"),
-            Tile::Waypoint { id } => write!(f, "{}", id),
+            Tile::Floor => '.',
+            Tile::Wall => '#',
+            // ids above 35 have no single-character representation
+            Tile::Waypoint { id } => std::char::from_digit(u32::from(*id), 36).unwrap_or('?'),
         }
     }
 }
 
-fn shortest_paths(from: &[i16; 2], map: &Map<[i16; 2], Tile>) -> HashMap<[i16; 2], usize> {
-    let mut dist: HashMap<[i16; 2], usize> = HashMap::new();
-    let mut queue: VecDeque<([i16; 2], usize)> = VecDeque::new();
-    queue.push_back((*from, 0));
-
-    while let Some((current, d)) = queue.pop_front() {
-        if !dist.contains_key(&current) {
-            dist.insert(current, d);
-
-            for (iofs, jofs) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
-                let n = [current[0] + iofs, current[1] + jofs];
-                if let Some(t) = map.get(&n) {
-                    if t != &Tile::Wall {
-                        queue.push_back((n, d + 1));
-                    }
-                }
-            }
-        }
-    }
-
-    dist
-}
-
 fn shortest_tour(
     from: u8,
     return_to: Option<u8>,
@@ -124,7 +102,8 @@ fn shortest_tour(
 
 fn main() -> Result<()> {
     let map: Map<[i16; 2], Tile> =
-        Map::read(&mut File::open("data/day24/input").context(Io)?).context(ParseMap)?;
+        Map::read(&mut File::open(aoc2016::input::input_path_in("data", 24)).context(Io)?)
+            .context(ParseMap)?;
 
     // let map: Map<[i16; 2], Tile> =
     //     "###########\n#0.1.....2#\n#.#######.#\n#4.......3#\n###########"
@@ -159,9 +138,9 @@ fn main() -> Result<()> {
 
     let mut wpsp: HashMap<(u8, u8), usize> = HashMap::new();
     for (i, p) in waypoint_positions.iter() {
-        let sp = shortest_paths(p, &map);
+        let sp = map.distances_from(*p, Connectivity::Four, |t| t != &Tile::Wall);
         for (j, q) in waypoint_positions.iter() {
-            wpsp.insert((*i, *j), sp[q]);
+            wpsp.insert((*i, *j), sp[q] as usize);
         }
     }
 
@@ -188,4 +167,15 @@ mod tests {
     fn it_works() -> Result<()> {
         Ok(())
     }
+
+    #[test]
+    fn test_waypoint_to_char() {
+        assert_eq!(Tile::Waypoint { id: 3 }.to_char(), '3');
+        assert_eq!(Tile::Waypoint { id: 12 }.to_char(), 'c');
+        assert_eq!(Tile::Waypoint { id: 255 }.to_char(), '?');
+        assert_eq!(
+            Tile::from_char(Tile::Waypoint { id: 7 }.to_char()),
+            Some(Tile::Waypoint { id: 7 })
+        );
+    }
 }
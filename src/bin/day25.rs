@@ -1,6 +1,6 @@
-use std::{collections::HashSet, io::Write};
+use std::collections::HashMap;
 
-use aoc2016::asmbunny::{AsmError, Instruction, State, StepResult};
+use aoc2016::asmbunny::{parse_program, AsmError, Instruction, State, StepResult};
 use snafu::{ResultExt, Snafu};
 
 type Result<T> = std::result::Result<T, Error>;
@@ -14,54 +14,78 @@ enum Error {
     Asm { source: AsmError },
 }
 
-fn main() -> Result<()> {
-    let instructions: Vec<Instruction> = std::fs::read_to_string("data/day25/input")
-        .context(Io)?
-        .lines()
-        .map(|l| l.parse().context(Asm))
-        .collect::<Result<_>>()?;
-
-    let mut a = 1;
-    loop {
-        let mut state = State::from_instructions(instructions.clone());
-        state.registers[0] = a;
-
-        print!("\na = {:5}: ", a);
-
-        let mut found = true;
-        let mut seen_states = HashSet::new();
-        let mut n_outputs = 0;
-        while !seen_states.contains(&state) {
-            seen_states.insert(state.clone());
-
-            match state.step() {
-                aoc2016::asmbunny::StepResult::OutOfProgram => {
-                    found = false;
-                    println!("\nOut of program!");
-                    break;
-                }
-                aoc2016::asmbunny::StepResult::OkNoOutput => {}
-                aoc2016::asmbunny::StepResult::OkOutput { out } => {
-                    print!("{}", out);
-                    if out != n_outputs % 2 {
-                        found = false;
-                        //println!("\nInvalid output #{}: {}", n_outputs, out);
-                    }
-                    n_outputs += 1;
-
-                    std::io::stdout().flush().unwrap();
+/// Check whether running the program with register `a` set to `seed` produces an endless
+/// clock signal 0, 1, 0, 1, ... This is the case if the whole state repeats after an even
+/// number of correct outputs. Gives up after `max_steps` steps.
+fn is_clock_signal(instructions: &[Instruction], seed: i64, max_steps: usize) -> bool {
+    let mut state = State::from_slice(instructions);
+    state["a"] = seed;
+
+    // number of outputs so far for every state seen
+    let mut seen_states: HashMap<State, usize> = HashMap::new();
+    let mut n_outputs = 0;
+
+    for _ in 0..max_steps {
+        if let Some(before) = seen_states.insert(state.clone(), n_outputs) {
+            let period = n_outputs - before;
+            return period > 0 && period % 2 == 0;
+        }
+
+        match state.step() {
+            StepResult::OutOfProgram => return false,
+            StepResult::OkNoOutput | StepResult::OkToggle { .. } => {}
+            StepResult::OkOutput { out } => {
+                if out != (n_outputs % 2) as i64 {
+                    return false;
                 }
+                n_outputs += 1;
             }
         }
-        // println!("\nLooping after {} states!", seen_states.len());
+    }
 
-        if found && n_outputs >= 2 {
-            println!("\nPart 1: a={}", a);
-            break;
-        }
+    false
+}
 
-        a += 1;
-    }
+/// Find the smallest positive seed that produces a clock signal
+#[cfg(any(test, not(feature = "rayon")))]
+fn find_seed(instructions: &[Instruction], max_steps: usize) -> i64 {
+    (1..)
+        .find(|seed| is_clock_signal(instructions, *seed, max_steps))
+        .unwrap()
+}
+
+/// Find the smallest positive seed that produces a clock signal, checking batches of
+/// seeds in parallel
+#[cfg(feature = "rayon")]
+fn find_seed_parallel(instructions: &[Instruction], max_steps: usize) -> i64 {
+    use rayon::prelude::*;
+
+    const BATCH_SIZE: i64 = 64;
+
+    (0..)
+        .find_map(|batch| {
+            let first = 1 + batch * BATCH_SIZE;
+            (first..first + BATCH_SIZE)
+                .into_par_iter()
+                .filter(|seed| is_clock_signal(instructions, *seed, max_steps))
+                .min()
+        })
+        .unwrap()
+}
+
+const MAX_STEPS: usize = 1_000_000;
+
+fn main() -> Result<()> {
+    let instructions: Vec<Instruction> =
+        parse_program(&aoc2016::read_input(25).context(Io)?).context(Asm)?;
+
+    #[cfg(feature = "rayon")]
+    let seed = find_seed_parallel(&instructions, MAX_STEPS);
+
+    #[cfg(not(feature = "rayon"))]
+    let seed = find_seed(&instructions, MAX_STEPS);
+
+    println!("Part 1: a={}", seed);
 
     Ok(())
 }
@@ -70,8 +94,41 @@ fn main() -> Result<()> {
 mod tests {
     use super::*;
 
+    /// Emits `a - 3, a - 2` forever, which is a clock signal only for `a = 3`
+    const SYNTHETIC: &str = "dec a\ndec a\ndec a\nout a\ninc a\nout a\ndec a\njnz 1 -4";
+
     #[test]
-    fn it_works() -> Result<()> {
+    fn test_is_clock_signal() -> Result<()> {
+        let instructions = parse_program(SYNTHETIC).context(Asm)?;
+        assert!(is_clock_signal(&instructions, 3, 1000));
+        assert!(!is_clock_signal(&instructions, 4, 1000));
+        assert!(!is_clock_signal(&instructions, 3, 5));
+
+        // an odd number of outputs per loop continues with the wrong value
+        let odd = parse_program("out a\njnz 1 -1").context(Asm)?;
+        assert!(!is_clock_signal(&odd, 0, 1000));
+
+        // writes to constants don't keep the state from repeating
+        let constant =
+            parse_program("out a\ninc a\ncpy 1 2\nout a\ndec a\njnz 1 -5").context(Asm)?;
+        assert!(is_clock_signal(&constant, 0, 1000));
+
+        assert_eq!(find_seed(&instructions, 1000), 3);
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_find_seed_parallel() -> Result<()> {
+        let instructions = parse_program(SYNTHETIC).context(Asm)?;
+        assert_eq!(
+            find_seed_parallel(&instructions, 1000),
+            find_seed(&instructions, 1000)
+        );
+
+        let late = parse_program(&format!("{}{}", "dec a\n".repeat(97), SYNTHETIC)).context(Asm)?;
+        assert_eq!(find_seed_parallel(&late, 1000), 100);
+        assert_eq!(find_seed(&late, 1000), 100);
         Ok(())
     }
 }
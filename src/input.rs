@@ -0,0 +1,131 @@
+use snafu::{ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Snafu)]
+pub enum InputError {
+    #[snafu(display("I/O error: {}", source))]
+    Io { source: std::io::Error },
+
+    #[snafu(display("Cannot parse {} line(s), first at {}", errors.len(), errors[0]))]
+    ParseLines { errors: Vec<LineError> },
+}
+
+/// A single line of input that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineError {
+    pub line: usize,
+    pub data: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: '{}': {}", self.line, self.data, self.message)
+    }
+}
+
+/// Location of the puzzle input for `day` below the directory `base`.
+pub fn input_path_in<P: AsRef<Path>>(base: P, day: u32) -> PathBuf {
+    base.as_ref().join(format!("day{:02}", day)).join("input")
+}
+
+/// Read the puzzle input for `day` from `data/dayNN/input`.
+pub fn read_input(day: u32) -> Result<String, std::io::Error> {
+    read_input_in("data", day)
+}
+
+/// Read the puzzle input for `day` from `base/dayNN/input`.
+pub fn read_input_in<P: AsRef<Path>>(base: P, day: u32) -> Result<String, std::io::Error> {
+    std::fs::read_to_string(input_path_in(base, day))
+}
+
+/// Parse every non-blank line of `input` into a `T`, collecting all lines that fail.
+pub fn parse_lines<T>(input: &str) -> Result<Vec<T>, InputError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let mut values = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, l) in input.lines().enumerate() {
+        if l.trim().is_empty() {
+            continue;
+        }
+
+        match l.parse::<T>() {
+            Ok(v) => values.push(v),
+            Err(e) => errors.push(LineError {
+                line: i + 1,
+                data: l.to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(InputError::ParseLines { errors })
+    }
+}
+
+/// Read the puzzle input for `day` and parse each line into a `T`.
+pub fn read_lines_parsed<T>(day: u32) -> Result<Vec<T>, InputError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    read_lines_parsed_in("data", day)
+}
+
+/// Read the puzzle input for `day` below `base` and parse each line into a `T`.
+pub fn read_lines_parsed_in<T, P>(base: P, day: u32) -> Result<Vec<T>, InputError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+    P: AsRef<Path>,
+{
+    parse_lines(&read_input_in(base, day).context(Io)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_path() {
+        assert_eq!(input_path_in("data", 7), Path::new("data/day07/input"));
+        assert_eq!(input_path_in("data", 23), Path::new("data/day23/input"));
+    }
+
+    #[test]
+    fn test_read_lines_parsed() {
+        let base = std::env::temp_dir().join(format!("aoc2016-input-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("day07")).unwrap();
+        std::fs::write(base.join("day07").join("input"), "1\n22\n\n333\n").unwrap();
+
+        assert_eq!(read_input_in(&base, 7).unwrap(), "1\n22\n\n333\n");
+
+        let values: Vec<u32> = read_lines_parsed_in(&base, 7).unwrap();
+        assert_eq!(values, vec![1, 22, 333]);
+
+        std::fs::write(base.join("day07").join("input"), "1\nx\n\n4\n-5\n").unwrap();
+        match read_lines_parsed_in::<u32, _>(&base, 7) {
+            Err(InputError::ParseLines { errors }) => {
+                let failed: Vec<(usize, &str)> =
+                    errors.iter().map(|e| (e.line, &e.data[..])).collect();
+                assert_eq!(failed, vec![(2, "x"), (5, "-5")]);
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        assert!(matches!(
+            read_lines_parsed_in::<u32, _>(&base, 8),
+            Err(InputError::Io { .. })
+        ));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
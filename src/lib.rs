@@ -1,2 +1,14 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod asmbunny;
+#[cfg(feature = "std")]
+pub mod input;
+#[cfg(feature = "std")]
 pub mod map;
+#[cfg(feature = "std")]
+pub mod search;
+
+#[cfg(feature = "std")]
+pub use input::{read_input, read_lines_parsed};
@@ -1,12 +1,18 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet, VecDeque};
 
 use snafu::{ResultExt, Snafu};
 use std::io::{BufRead, BufReader};
 
+use crate::search::astar;
+
 #[derive(Debug, Snafu)]
 pub enum MapError {
     #[snafu(display("I/O error: {}", source))]
     Io { source: std::io::Error },
+
+    #[snafu(display("Unknown tile '{}' at row {}, column {}", c, row, col))]
+    UnknownTile { c: char, row: usize, col: usize },
 }
 
 type MapResult<T> = std::result::Result<T, MapError>;
@@ -36,14 +42,32 @@ impl<T> IntCoord for T where
 }
 
 /// Trait for types that can correspond to map tiles
-pub trait MapTile: std::fmt::Display + Sized + Clone {}
+pub trait MapTile: Sized + Clone {
+    /// Get the character used to display the tile
+    fn to_char(&self) -> char;
+}
 
-// auto-implement for types that meet requirements
-impl<T> MapTile for T where T: std::fmt::Display + Sized + Clone {}
+impl MapTile for char {
+    fn to_char(&self) -> char {
+        *self
+    }
+}
 
 /// Trait for types that can be parsed as a map tile
 pub trait ParseMapTile: MapTile {
+    /// Parse a tile, returning `None` for characters that are not valid tiles
     fn from_char(c: char) -> Option<Self>;
+
+    /// Check whether a character marks a gap in the map. Blank cells are left unset when
+    /// parsing, while other characters that `from_char` rejects are errors.
+    fn is_blank(c: char) -> bool {
+        c == ' '
+    }
+}
+
+/// Check that a tile survives a round trip through its character representation
+pub fn roundtrips_char<T: ParseMapTile + PartialEq>(tile: &T) -> bool {
+    T::from_char(tile.to_char()).as_ref() == Some(tile)
 }
 
 /// Trait for a generic map coordinate
@@ -72,13 +96,58 @@ pub trait MapCoordinate: Default + Eq + std::hash::Hash + std::fmt::Debug + Clon
 
     /// Get an iterator spanning all coordinates within an extent
     fn extent_iterator(min: Self, max: Self) -> Self::ExtentIter;
+
+    /// Check whether the coordinate lies within the extent from `min` to `max` (inclusive)
+    fn is_within(&self, min: &Self, max: &Self) -> bool {
+        MapCoordinate::elementwise_min(*self, *min) == *min
+            && MapCoordinate::elementwise_max(*self, *max) == *max
+    }
 }
 
 /// A tile-based map that is generic over coordinates and tiles stored within
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "C: serde::Serialize, T: serde::Serialize",
+        deserialize = "C: serde::Deserialize<'de>, T: serde::Deserialize<'de>"
+    ))
+)]
 pub struct Map<C: MapCoordinate, T> {
+    #[cfg_attr(feature = "serde", serde(with = "serde_entries"))]
     pub data: HashMap<C, T>,
     pub fixed_extent: Option<(C, C)>,
+    /// Tile returned by `get` for coordinates without a tile
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub default: Option<T>,
+}
+
+/// Serialize tile data as a list of `(coordinate, tile)` entries, since
+/// most serialization formats only allow string keys in maps
+#[cfg(feature = "serde")]
+mod serde_entries {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    pub fn serialize<S, C, T>(data: &HashMap<C, T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        C: Serialize,
+        T: Serialize,
+    {
+        serializer.collect_seq(data.iter())
+    }
+
+    pub fn deserialize<'de, D, C, T>(deserializer: D) -> Result<HashMap<C, T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        C: Deserialize<'de> + Eq + std::hash::Hash,
+        T: Deserialize<'de>,
+    {
+        let entries: Vec<(C, T)> = Vec::deserialize(deserializer)?;
+        Ok(entries.into_iter().collect())
+    }
 }
 
 impl<C: MapCoordinate, T> Map<C, T> {
@@ -86,12 +155,61 @@ impl<C: MapCoordinate, T> Map<C, T> {
         Map {
             data: HashMap::new(),
             fixed_extent: None,
+            default: None,
+        }
+    }
+
+    /// Create an empty map where lookups return `default` for all coordinates within the
+    /// extent that have no tile
+    pub fn with_default(default: T) -> Self {
+        Map {
+            default: Some(default),
+            ..Map::new()
         }
     }
 
-    /// Get the tile at a coordinate
+    /// The extent within which the default tile fills gaps, if the map has a default
+    fn default_extent(&self) -> Option<(C, C)> {
+        if self.default.is_none() || (self.data.is_empty() && self.fixed_extent.is_none()) {
+            None
+        } else {
+            Some(self.get_extent())
+        }
+    }
+
+    /// Look up a tile, falling back to the default tile within `extent`, as returned by
+    /// `default_extent`. Computing the extent once lets callers do many lookups cheaply.
+    fn lookup(&self, coord: &C, extent: Option<&(C, C)>) -> Option<&T> {
+        self.data.get(coord).or_else(|| match extent {
+            Some((min, max)) if coord.is_within(min, max) => self.default.as_ref(),
+            _ => None,
+        })
+    }
+
+    /// Get the tile at a coordinate, or the map's default tile if there is none and the
+    /// coordinate lies within the extent. Finding the extent for a missing tile takes a
+    /// scan over all tiles unless the extent is fixed.
     pub fn get(&self, coord: &C) -> Option<&T> {
-        self.data.get(coord)
+        self.data
+            .get(coord)
+            .or_else(|| self.lookup(coord, self.default_extent().as_ref()))
+    }
+
+    /// Get a copy of the tile at a coordinate
+    pub fn get_cloned(&self, coord: &C) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.get(coord).cloned()
+    }
+
+    /// Get a copy of the tile at a coordinate, or `default` if there is no tile. The
+    /// map's own default tile is not used.
+    pub fn get_or(&self, coord: &C, default: T) -> T
+    where
+        T: Clone,
+    {
+        self.data.get(coord).cloned().unwrap_or(default)
     }
 
     /// Get a mutable reference to a tile at a coordinate
@@ -104,6 +222,11 @@ impl<C: MapCoordinate, T> Map<C, T> {
         self.data.insert(coord, value);
     }
 
+    /// Set the tiles at all coordinates produced by an iterator
+    pub fn extend_from<I: IntoIterator<Item = (C, T)>>(&mut self, iter: I) {
+        self.data.extend(iter);
+    }
+
     /// Clear a coordinate from tiles
     pub fn remove(&mut self, coord: &C) {
         self.data.remove(coord);
@@ -118,6 +241,46 @@ impl<C: MapCoordinate, T> Map<C, T> {
         }
     }
 
+    /// Replace every tile with the result of `f` on it
+    pub fn apply<F: Fn(&T) -> T>(&mut self, f: F) {
+        for tile in self.data.values_mut() {
+            *tile = f(tile);
+        }
+    }
+
+    /// Set every cell between `min` and `max` (inclusive) that has no tile yet to `fill`
+    pub fn ensure_filled(&mut self, min: C, max: C, fill: T)
+    where
+        T: Clone,
+    {
+        for coord in C::extent_iterator(min, max) {
+            self.data.entry(coord).or_insert_with(|| fill.clone());
+        }
+    }
+
+    /// Copy all tiles of `other` into this map, replacing existing tiles
+    pub fn overlay(&mut self, other: &Self)
+    where
+        T: Clone,
+    {
+        for (coord, tile) in other.data.iter() {
+            self.data.insert(*coord, tile.clone());
+        }
+    }
+
+    /// Get the tight extent of all tiles that match a predicate, or `None` if none match
+    pub fn bounds_where<P: Fn(&T) -> bool>(&self, predicate: P) -> Option<(C, C)> {
+        let mut keys = self
+            .data
+            .iter()
+            .filter(|(_, t)| predicate(t))
+            .map(|(c, _)| *c)
+            .peekable();
+
+        keys.peek()?;
+        Some(C::get_extent(keys))
+    }
+
     /// Find all coordinates that match a predicate
     pub fn find_all_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> Vec<C> {
         let mut out: Vec<C> = Vec::new();
@@ -129,6 +292,24 @@ impl<C: MapCoordinate, T> Map<C, T> {
         out
     }
 
+    /// Iterate over all tiles in the order given by a comparator on their coordinates
+    pub fn iter_sorted_by<F: Fn(&C, &C) -> std::cmp::Ordering>(
+        &self,
+        cmp: F,
+    ) -> impl Iterator<Item = (&C, &T)> {
+        let mut entries: Vec<(&C, &T)> = self.data.iter().collect();
+        entries.sort_by(|a, b| cmp(a.0, b.0));
+        entries.into_iter()
+    }
+
+    /// Count the tiles that match a predicate
+    pub fn count_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> usize {
+        self.data
+            .iter()
+            .filter(|(coord, tile)| predicate(coord, tile))
+            .count()
+    }
+
     /// Find a coordinate that matches a predicate
     pub fn find_one_where<P: Fn(&C, &T) -> bool>(&self, predicate: P) -> Option<C> {
         for (coord, tile) in self.data.iter() {
@@ -140,6 +321,121 @@ impl<C: MapCoordinate, T> Map<C, T> {
     }
 }
 
+impl<C: MapCoordinate, T> Extend<(C, T)> for Map<C, T> {
+    fn extend<I: IntoIterator<Item = (C, T)>>(&mut self, iter: I) {
+        self.extend_from(iter);
+    }
+}
+
+impl<C: MapCoordinate + Ord, T> Map<C, T> {
+    /// Find the smallest coordinate whose tile matches a predicate
+    pub fn find<P: Fn(&T) -> bool>(&self, predicate: P) -> Option<C> {
+        self.data
+            .iter()
+            .filter(|(_, t)| predicate(t))
+            .map(|(c, _)| c)
+            .min()
+            .cloned()
+    }
+
+    /// Find all coordinates whose tile matches a predicate, in coordinate order
+    pub fn find_all_sorted<P: Fn(&T) -> bool>(&self, predicate: P) -> Vec<C> {
+        let mut out = self.find_all_where(|_, t| predicate(t));
+        out.sort();
+        out
+    }
+}
+
+/// Iterate over all tiles in coordinate order
+impl<'a, C: MapCoordinate + Ord, T> IntoIterator for &'a Map<C, T> {
+    type Item = (&'a C, &'a T);
+    type IntoIter = std::vec::IntoIter<(&'a C, &'a T)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut entries: Vec<(&C, &T)> = self.data.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<C: MapCoordinate + Sync, T: Sync> Map<C, T> {
+    /// Count the tiles that match a predicate like `count_where`, checking tiles in parallel
+    pub fn par_count_where<P: Fn(&C, &T) -> bool + Sync>(&self, predicate: P) -> usize {
+        use rayon::prelude::*;
+
+        self.data
+            .par_iter()
+            .filter(|(coord, tile)| predicate(coord, tile))
+            .count()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Sync, I: IntCoord + Send + Sync> Map<[I; 2], T> {
+    /// Compute the same distances as `distances_from`, finding the neighbors of each BFS
+    /// layer in parallel
+    pub fn par_distances_from<P: Fn(&T) -> bool + Sync>(
+        &self,
+        start: [I; 2],
+        connectivity: Connectivity,
+        passable: P,
+    ) -> HashMap<[I; 2], u32> {
+        use rayon::prelude::*;
+
+        let mut dist: HashMap<[I; 2], u32> = HashMap::new();
+        let mut frontier = vec![start];
+        dist.insert(start, 0);
+
+        let mut d = 0;
+        while !frontier.is_empty() {
+            d += 1;
+            let candidates: Vec<[I; 2]> = frontier
+                .par_iter()
+                .flat_map_iter(|pos| self.passable_neighbors(*pos, connectivity, &passable))
+                .collect();
+
+            frontier = Vec::new();
+            for n in candidates {
+                if let Entry::Vacant(e) = dist.entry(n) {
+                    e.insert(d);
+                    frontier.push(n);
+                }
+            }
+        }
+
+        dist
+    }
+}
+
+impl<C: MapCoordinate, T: std::hash::Hash> Map<C, T> {
+    /// Get a fingerprint of all tiles that does not depend on insertion order. Maps with
+    /// equal tiles have equal checksums within one build of the program.
+    pub fn checksum(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        self.data
+            .iter()
+            .map(|entry| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                entry.hash(&mut hasher);
+                hasher.finish()
+            })
+            .fold(0, |acc, h| acc ^ h)
+    }
+}
+
+impl<C: MapCoordinate, T: Eq + std::hash::Hash + Clone> Map<C, T> {
+    /// Count how often each tile occurs in the map
+    pub fn histogram(&self) -> HashMap<T, usize> {
+        let mut counts: HashMap<T, usize> = HashMap::new();
+        for tile in self.data.values() {
+            *counts.entry(tile.clone()).or_default() += 1;
+        }
+        counts
+    }
+}
+
 impl<C: MapCoordinate, T: Eq> Map<C, T> {
     /// Find all coordinates that contain a tile
     pub fn find_all(&self, pattern: &T) -> Vec<C> {
@@ -221,61 +517,731 @@ where
     I: IntCoord,
 {
     pub fn read<R: std::io::Read>(reader: &mut R) -> MapResult<Self> {
-        let mut data: HashMap<[I; 2], T> = HashMap::new();
+        let mut map = Map::new();
 
         let buf_reader = BufReader::new(reader);
         for (i, line) in buf_reader.lines().enumerate() {
-            for (j, c) in line.context(Io)?.chars().enumerate() {
-                if let Some(t) = T::from_char(c) {
-                    if let (Some(i), Some(j)) = (I::from_usize(i), I::from_usize(j)) {
-                        data.insert([i, j], t);
-                    }
-                }
-            }
+            map.parse_row(i, &line.context(Io)?)?;
         }
 
-        Ok(Map {
-            data,
-            fixed_extent: None,
-        })
+        Ok(map)
+    }
+
+    /// Build a map from lines that have already been split, with line `i` becoming row `i`
+    pub fn from_lines<'a, L: IntoIterator<Item = &'a str>>(lines: L) -> MapResult<Self> {
+        let mut map = Map::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            map.parse_row(i, line)?;
+        }
+        Ok(map)
+    }
+
+    fn parse_row(&mut self, i: usize, line: &str) -> MapResult<()> {
+        for (j, c) in line.chars().enumerate() {
+            if T::is_blank(c) {
+                continue;
+            }
+
+            let t = T::from_char(c).ok_or(MapError::UnknownTile { c, row: i, col: j })?;
+            if let (Some(i), Some(j)) = (I::from_usize(i), I::from_usize(j)) {
+                self.data.insert([i, j], t);
+            }
+        }
+        Ok(())
     }
 
     pub fn to_vecs(&self) -> Vec<Vec<Option<T>>> {
         let (min, max) = self.get_extent();
+        let extent = self.default_extent();
 
         num::iter::range_inclusive(min[0], max[0])
             .map(|i| {
                 num::iter::range_inclusive(min[1], max[1])
-                    .map(|j| self.data.get(&[i, j]).cloned())
+                    .map(|j| self.lookup(&[i, j], extent.as_ref()).cloned())
                     .collect()
             })
             .collect()
     }
 }
 
-impl<T, I> std::fmt::Display for Map<[I; 2], T>
+/// Which neighbors of a tile in a 2D map can be moved to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the orthogonal neighbors
+    Four,
+    /// The orthogonal and diagonal neighbors
+    Eight,
+}
+
+impl Connectivity {
+    /// Get the offsets to all neighbors of a 2D coordinate
+    pub fn offsets(&self) -> &'static [[i64; 2]] {
+        match self {
+            Connectivity::Four => &[[-1, 0], [1, 0], [0, -1], [0, 1]],
+            Connectivity::Eight => &[
+                [-1, -1],
+                [-1, 0],
+                [-1, 1],
+                [0, -1],
+                [0, 1],
+                [1, -1],
+                [1, 0],
+                [1, 1],
+            ],
+        }
+    }
+
+    /// Get the minimum number of steps between two coordinates on an open map, i.e.
+    /// the Manhattan distance for `Four` and the Chebyshev distance for `Eight`
+    pub fn distance<I: IntCoord>(&self, a: &[I; 2], b: &[I; 2]) -> usize {
+        let di = (a[0].to_i64().unwrap_or(0) - b[0].to_i64().unwrap_or(0)).unsigned_abs();
+        let dj = (a[1].to_i64().unwrap_or(0) - b[1].to_i64().unwrap_or(0)).unsigned_abs();
+
+        match self {
+            Connectivity::Four => (di + dj) as usize,
+            Connectivity::Eight => std::cmp::max(di, dj) as usize,
+        }
+    }
+}
+
+/// Offset a 2D coordinate, returning `None` if the result cannot be represented
+fn offset_2d<I: IntCoord>(coord: &[I; 2], offset: &[i64; 2]) -> Option<[I; 2]> {
+    let i = I::from_i64(coord[0].to_i64()? + offset[0])?;
+    let j = I::from_i64(coord[1].to_i64()? + offset[1])?;
+    Some([i, j])
+}
+
+/// Mirror a coordinate across the center of `min..=max`, returning `None` if it lies
+/// outside of that range
+fn mirror<I: IntCoord>(c: I, min: I, max: I) -> Option<I> {
+    if c < min || c > max {
+        return None;
+    }
+    Some(max - (c - min))
+}
+
+impl<T, I> Map<[I; 2], T>
+where
+    I: IntCoord,
+{
+    /// Set the tiles of row `i`, starting from column 0
+    pub fn push_row<R: IntoIterator<Item = T>>(&mut self, i: I, row: R) {
+        let mut j = I::zero();
+        for tile in row {
+            self.data.insert([i, j], tile);
+            j = j + I::one();
+        }
+    }
+
+    /// Get references to all tiles within the extent, row by row, with `None` for gaps
+    pub fn rows(&self) -> Vec<Vec<Option<&T>>> {
+        let (min, max) = self.get_extent();
+        let extent = self.default_extent();
+
+        num::iter::range_inclusive(min[0], max[0])
+            .map(|i| {
+                num::iter::range_inclusive(min[1], max[1])
+                    .map(|j| self.lookup(&[i, j], extent.as_ref()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Get references to all tiles within the extent, column by column, with `None` for gaps
+    pub fn columns(&self) -> Vec<Vec<Option<&T>>> {
+        let (min, max) = self.get_extent();
+        let extent = self.default_extent();
+
+        num::iter::range_inclusive(min[1], max[1])
+            .map(|j| {
+                num::iter::range_inclusive(min[0], max[0])
+                    .map(|i| self.lookup(&[i, j], extent.as_ref()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Check whether there is a tile at every coordinate within the extent
+    pub fn is_dense(&self) -> bool {
+        let (min, max) = self.get_extent();
+        num::iter::range_inclusive(min[0], max[0]).all(|i| {
+            num::iter::range_inclusive(min[1], max[1]).all(|j| self.data.contains_key(&[i, j]))
+        })
+    }
+
+    /// Get the corners of the extent: top left, top right, bottom left and bottom right
+    pub fn corner_coords(&self) -> [[I; 2]; 4] {
+        let (min, max) = self.get_extent();
+        [
+            [min[0], min[1]],
+            [min[0], max[1]],
+            [max[0], min[1]],
+            [max[0], max[1]],
+        ]
+    }
+
+    /// Get all coordinates on the border of the extent, row by row
+    pub fn edge_coords(&self) -> Vec<[I; 2]> {
+        let (min, max) = self.get_extent();
+
+        num::iter::range_inclusive(min[0], max[0])
+            .flat_map(|i| num::iter::range_inclusive(min[1], max[1]).map(move |j| [i, j]))
+            .filter(|[i, j]| *i == min[0] || *i == max[0] || *j == min[1] || *j == max[1])
+            .collect()
+    }
+
+    /// Get a copy of the map where every coordinate is shifted by `delta`. Tiles whose
+    /// new coordinate cannot be represented by the coordinate type are dropped.
+    pub fn translate(&self, delta: [i64; 2]) -> Self
+    where
+        T: Clone,
+    {
+        let data = self
+            .data
+            .iter()
+            .filter_map(|(k, t)| offset_2d(k, &delta).map(|k| (k, t.clone())))
+            .collect();
+
+        let fixed_extent = self
+            .fixed_extent
+            .and_then(|(min, max)| Some((offset_2d(&min, &delta)?, offset_2d(&max, &delta)?)));
+
+        Map {
+            data,
+            fixed_extent,
+            default: self.default.clone(),
+        }
+    }
+
+    /// Shift all coordinates so that the minimum corner of the extent becomes `[0, 0]`,
+    /// returning the offset that was added to every coordinate
+    pub fn normalize(&mut self) -> [i64; 2] {
+        let (min, _) = self.get_extent();
+        let delta = [
+            -min[0].to_i64().expect("row fits into i64"),
+            -min[1].to_i64().expect("column fits into i64"),
+        ];
+
+        self.data = self
+            .data
+            .drain()
+            .filter_map(|(k, t)| offset_2d(&k, &delta).map(|k| (k, t)))
+            .collect();
+        self.fixed_extent = self
+            .fixed_extent
+            .and_then(|(min, max)| Some((offset_2d(&min, &delta)?, offset_2d(&max, &delta)?)));
+
+        delta
+    }
+
+    /// Get a copy of the map with rows and columns swapped. The top left corner of the
+    /// extent stays in place.
+    pub fn transpose(&self) -> Self
+    where
+        T: Clone,
+    {
+        if self.data.is_empty() && self.fixed_extent.is_none() {
+            return Map::new();
+        }
+
+        let (min, _) = self.get_extent();
+        let swap = |k: &[I; 2]| {
+            // offsets are taken in i64 so that unsigned coordinates cannot underflow
+            let di = k[1].to_i64()? - min[1].to_i64()?;
+            let dj = k[0].to_i64()? - min[0].to_i64()?;
+            offset_2d(&min, &[di, dj])
+        };
+
+        Map {
+            data: self
+                .data
+                .iter()
+                .filter_map(|(k, t)| Some((swap(k)?, t.clone())))
+                .collect(),
+            fixed_extent: self
+                .fixed_extent
+                .and_then(|(min, max)| Some((min, swap(&max)?))),
+            default: self.default.clone(),
+        }
+    }
+
+    /// Check whether every row reads the same from left to right and from right to left,
+    /// mirroring columns across the center of the extent
+    pub fn has_horizontal_symmetry(&self) -> bool
+    where
+        T: PartialEq,
+    {
+        let (min, max) = self.get_extent();
+        self.data.iter().all(|(k, t)| {
+            mirror(k[1], min[1], max[1]).and_then(|j| self.data.get(&[k[0], j])) == Some(t)
+        })
+    }
+
+    /// Check whether every column reads the same from top to bottom and from bottom to
+    /// top, mirroring rows across the center of the extent
+    pub fn has_vertical_symmetry(&self) -> bool
+    where
+        T: PartialEq,
+    {
+        let (min, max) = self.get_extent();
+        self.data.iter().all(|(k, t)| {
+            mirror(k[0], min[0], max[0]).and_then(|i| self.data.get(&[i, k[1]])) == Some(t)
+        })
+    }
+
+    /// Iterate over the tiles at the given offsets from a coordinate that are present
+    /// in the map
+    pub fn neighbors_with<'a>(
+        &'a self,
+        pos: [I; 2],
+        offsets: &'a [[i64; 2]],
+    ) -> impl Iterator<Item = ([I; 2], &'a T)> + 'a {
+        let extent = self.default_extent();
+        offsets
+            .iter()
+            .filter_map(move |ofs| offset_2d(&pos, ofs))
+            .filter_map(move |n| self.lookup(&n, extent.as_ref()).map(|t| (n, t)))
+    }
+
+    /// Count the neighbors of a coordinate that are present in the map and match a predicate
+    pub fn count_neighbors<P: Fn(&T) -> bool>(
+        &self,
+        pos: [I; 2],
+        connectivity: Connectivity,
+        predicate: P,
+    ) -> usize {
+        self.neighbors_with(pos, connectivity.offsets())
+            .filter(|(_, t)| predicate(t))
+            .count()
+    }
+
+    /// Iterate over the diagonal neighbors of a coordinate that are present in the map
+    pub fn neighbors_diagonal(&self, pos: [I; 2]) -> impl Iterator<Item = ([I; 2], &T)> + '_ {
+        self.neighbors_with(pos, &[[-1, -1], [-1, 1], [1, -1], [1, 1]])
+    }
+
+    /// Look up the four orthogonal neighbors of a coordinate (up, down, left, right), giving
+    /// each direction as an offset together with the tile there, or `None` if it is missing
+    pub fn neighbors4_opt(&self, pos: [I; 2]) -> [([i64; 2], Option<&T>); 4] {
+        let extent = self.default_extent();
+        let lookup = |ofs: [i64; 2]| {
+            let tile = offset_2d(&pos, &ofs).and_then(|n| self.lookup(&n, extent.as_ref()));
+            (ofs, tile)
+        };
+        [
+            lookup([-1, 0]),
+            lookup([1, 0]),
+            lookup([0, -1]),
+            lookup([0, 1]),
+        ]
+    }
+
+    /// Iterate over all tiles within a Manhattan distance of `radius` around `center`,
+    /// including `center` itself
+    pub fn within_manhattan(
+        &self,
+        center: [I; 2],
+        radius: i64,
+    ) -> impl Iterator<Item = ([I; 2], &T)> + '_ {
+        let extent = self.default_extent();
+        (-radius..=radius)
+            .flat_map(move |di| {
+                let rest = radius - di.abs();
+                (-rest..=rest).map(move |dj| [di, dj])
+            })
+            .filter_map(move |ofs| offset_2d(&center, &ofs))
+            .filter_map(move |n| self.lookup(&n, extent.as_ref()).map(|t| (n, t)))
+    }
+
+    /// Iterate over the neighbors of a coordinate that contain a passable tile
+    fn passable_neighbors<'a, P: Fn(&T) -> bool>(
+        &'a self,
+        pos: [I; 2],
+        connectivity: Connectivity,
+        passable: &'a P,
+    ) -> impl Iterator<Item = [I; 2]> + 'a {
+        self.neighbors_with(pos, connectivity.offsets())
+            .filter(move |(_, t)| passable(t))
+            .map(|(n, _)| n)
+    }
+
+    /// Check whether `goal` can be reached from `start` by moving between neighboring
+    /// tiles that are passable
+    pub fn path_exists<P: Fn(&T) -> bool>(
+        &self,
+        start: [I; 2],
+        goal: [I; 2],
+        connectivity: Connectivity,
+        passable: P,
+    ) -> bool {
+        let mut seen: HashSet<[I; 2]> = HashSet::new();
+        let mut queue: VecDeque<[I; 2]> = VecDeque::new();
+        seen.insert(start);
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            if pos == goal {
+                return true;
+            }
+
+            for n in self.passable_neighbors(pos, connectivity, &passable) {
+                if seen.insert(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Compute the number of steps to every tile reachable from `start` by moving
+    /// between neighboring tiles that are passable
+    pub fn distances_from<P: Fn(&T) -> bool>(
+        &self,
+        start: [I; 2],
+        connectivity: Connectivity,
+        passable: P,
+    ) -> HashMap<[I; 2], u32> {
+        let mut dist: HashMap<[I; 2], u32> = HashMap::new();
+        let mut queue: VecDeque<[I; 2]> = VecDeque::new();
+        dist.insert(start, 0);
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            let d = dist[&pos];
+
+            for n in self.passable_neighbors(pos, connectivity, &passable) {
+                if let Entry::Vacant(e) = dist.entry(n) {
+                    e.insert(d + 1);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        dist
+    }
+
+    /// Replace the connected region of tiles equal to the tile at `start` with `new`,
+    /// like a paint bucket
+    pub fn flood_fill_set(&mut self, start: [I; 2], new: T)
+    where
+        T: Clone + PartialEq,
+    {
+        let old = match self.data.get(&start) {
+            Some(old) if *old != new => old.clone(),
+            _ => return,
+        };
+
+        let mut queue: VecDeque<[I; 2]> = VecDeque::new();
+        self.data.insert(start, new.clone());
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            for ofs in Connectivity::Four.offsets() {
+                if let Some(n) = offset_2d(&pos, ofs) {
+                    if self.data.get(&n) == Some(&old) {
+                        self.data.insert(n, new.clone());
+                        queue.push_back(n);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Find all passable tiles that can be reached from the border of the extent by moving
+    /// between 4-connected passable tiles. Passable tiles that are not part of the result
+    /// lie in pockets enclosed by impassable ones.
+    pub fn exterior_cells<P: Fn(&T) -> bool>(&self, passable: P) -> HashSet<[I; 2]> {
+        let mut seen: HashSet<[I; 2]> = HashSet::new();
+        let mut queue: VecDeque<[I; 2]> = VecDeque::new();
+
+        let extent = self.default_extent();
+        for pos in self.edge_coords() {
+            let tile = self.lookup(&pos, extent.as_ref());
+            if tile.map(&passable).unwrap_or(false) && seen.insert(pos) {
+                queue.push_back(pos);
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            for n in self.passable_neighbors(pos, Connectivity::Four, &passable) {
+                if seen.insert(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        seen
+    }
+
+    /// Count the tiles matching a predicate that are 4-connected to `start` through other
+    /// matching tiles, including `start` itself
+    pub fn flood_count<P: Fn(&T) -> bool>(&self, start: [I; 2], matches: P) -> usize {
+        if !self.data.get(&start).map(&matches).unwrap_or(false) {
+            return 0;
+        }
+
+        // visited tiles are tracked in a bitmap, row by row. It spans the extent of the
+        // tiles rather than `get_extent`, since a fixed extent may not cover all of them.
+        let (min, max) = MapCoordinate::get_extent(self.data.keys().cloned());
+        let index = |pos: &[I; 2]| -> Option<usize> {
+            if pos[0] > max[0] || pos[1] > max[1] {
+                return None;
+            }
+            let i = pos[0].checked_sub(&min[0])?.to_usize()?;
+            let j = pos[1].checked_sub(&min[1])?.to_usize()?;
+            let width = max[1].checked_sub(&min[1])?.to_usize()? + 1;
+            Some(i * width + j)
+        };
+        let mut visited = vec![false; index(&max).unwrap_or(0) + 1];
+
+        let mut count = 0;
+        let mut stack = vec![start];
+        visited[index(&start).unwrap()] = true;
+
+        while let Some(pos) = stack.pop() {
+            count += 1;
+            for (n, t) in self.neighbors_with(pos, Connectivity::Four.offsets()) {
+                let idx = match index(&n) {
+                    Some(idx) => idx,
+                    None => continue,
+                };
+                if !visited[idx] && matches(t) {
+                    visited[idx] = true;
+                    stack.push(n);
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Find the number of steps on the shortest path from `start` to `goal` using A*
+    pub fn shortest_path_len<P: Fn(&T) -> bool>(
+        &self,
+        start: [I; 2],
+        goal: [I; 2],
+        connectivity: Connectivity,
+        passable: P,
+    ) -> Option<usize> {
+        astar(
+            start,
+            |pos| {
+                self.passable_neighbors(*pos, connectivity, &passable)
+                    .map(|n| (n, 1))
+                    .collect::<Vec<_>>()
+            },
+            |pos| connectivity.distance(pos, &goal),
+            |pos| *pos == goal,
+            None,
+        )
+    }
+
+    /// Find the cheapest path from `start` to `goal` where entering a tile costs
+    /// `cost(tile)`, and tiles with a cost of `None` are impassable. Returns the total
+    /// cost and the path including both endpoints.
+    pub fn dijkstra_grid<F: Fn(&T) -> Option<u32>>(
+        &self,
+        start: [I; 2],
+        goal: [I; 2],
+        connectivity: Connectivity,
+        cost: F,
+    ) -> Option<(u32, Vec<[I; 2]>)> {
+        let mut best: HashMap<[I; 2], u32> = HashMap::new();
+        let mut previous: HashMap<[I; 2], [I; 2]> = HashMap::new();
+        let mut queue: BinaryHeap<(Reverse<u32>, [I; 2])> = BinaryHeap::new();
+        best.insert(start, 0);
+        queue.push((Reverse(0), start));
+
+        while let Some((Reverse(c), pos)) = queue.pop() {
+            if pos == goal {
+                let mut path = vec![goal];
+                while let Some(p) = previous.get(path.last().unwrap()) {
+                    path.push(*p);
+                }
+                path.reverse();
+                return Some((c, path));
+            }
+
+            if best[&pos] < c {
+                continue;
+            }
+
+            for ofs in connectivity.offsets() {
+                let n = match offset_2d(&pos, ofs) {
+                    Some(n) => n,
+                    None => continue,
+                };
+
+                let step_cost = match self.data.get(&n).and_then(&cost) {
+                    Some(sc) => sc,
+                    None => continue,
+                };
+
+                let next_cost = c + step_cost;
+                if best.get(&n).map(|b| *b <= next_cost).unwrap_or(false) {
+                    continue;
+                }
+
+                best.insert(n, next_cost);
+                previous.insert(n, pos);
+                queue.push((Reverse(next_cost), n));
+            }
+        }
+
+        None
+    }
+}
+
+/// Run a cellular automaton for `steps` generations. In every generation, `rule` computes
+/// the new tile of each cell from the previous generation's map. Stops early once a
+/// generation no longer changes the map.
+pub fn simulate<T, I, F>(initial: Map<[I; 2], T>, steps: usize, rule: F) -> Map<[I; 2], T>
+where
+    T: Clone + PartialEq,
+    I: IntCoord,
+    F: Fn(&Map<[I; 2], T>, [I; 2]) -> T,
+{
+    let mut map = initial;
+    for _ in 0..steps {
+        let data: HashMap<[I; 2], T> = map
+            .data
+            .keys()
+            .map(|coord| (*coord, rule(&map, *coord)))
+            .collect();
+
+        if data == map.data {
+            break;
+        }
+        map.data = data;
+    }
+    map
+}
+
+/// A repetition found by `find_fixed_point_or_cycle`: generation `start + period` is
+/// equal to generation `start`. A fixed point has a period of 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleInfo {
+    pub start: usize,
+    pub period: usize,
+}
+
+/// Repeatedly apply `step` to a map until a generation equals an earlier one, computing
+/// at most `max` generations. Returns `None` if no repetition was found.
+pub fn find_fixed_point_or_cycle<T, I, F>(
+    initial: Map<[I; 2], T>,
+    step: F,
+    max: usize,
+) -> Option<CycleInfo>
+where
+    T: Eq + std::hash::Hash,
+    I: IntCoord,
+    F: Fn(&Map<[I; 2], T>) -> Map<[I; 2], T>,
+{
+    let mut generations: Vec<Map<[I; 2], T>> = Vec::new();
+    let mut by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut current = initial;
+
+    for index in 0..=max {
+        let hash = current.checksum();
+        let candidates = by_hash.entry(hash).or_default();
+        if let Some(start) = candidates
+            .iter()
+            .find(|i| generations[**i].data == current.data)
+        {
+            return Some(CycleInfo {
+                start: *start,
+                period: index - start,
+            });
+        }
+        candidates.push(index);
+
+        if index < max {
+            let next = step(&current);
+            generations.push(current);
+            current = next;
+        }
+    }
+
+    None
+}
+
+impl<T, I> Map<[I; 2], T>
 where
     T: MapTile,
     I: IntCoord,
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+    /// Render the map like `Display` does, but show the given characters instead of the
+    /// tiles at marked positions. Markers outside of the extent are not shown.
+    pub fn display_with_markers(&self, markers: &HashMap<[I; 2], char>) -> String {
+        let mut out = String::new();
         if self.data.is_empty() {
-            return Ok(());
+            return out;
         }
 
         let (min, max) = self.get_extent();
+        let extent = self.default_extent();
 
         for i in num::iter::range_inclusive(min[0], max[0]) {
             for j in num::iter::range_inclusive(min[1], max[1]) {
-                match self.data.get(&[i, j]) {
-                    Some(t) => t.fmt(f),
-                    None => write!(f, " "),
-                }?;
+                out.push(
+                    match (markers.get(&[i, j]), self.lookup(&[i, j], extent.as_ref())) {
+                        (Some(m), _) => *m,
+                        (None, Some(t)) => t.to_char(),
+                        (None, None) => ' ',
+                    },
+                );
             }
-            write!(f, "\n")?;
+            out.push('\n');
         }
 
-        Ok(())
+        out
+    }
+
+    /// Render the map with column indices along the top and row indices down the left.
+    /// Multi-digit column indices are written top to bottom.
+    pub fn to_string_with_axes(&self) -> String {
+        let mut out = String::new();
+        if self.data.is_empty() {
+            return out;
+        }
+
+        let (min, max) = self.get_extent();
+        let label_width = |lo: I, hi: I| {
+            num::iter::range_inclusive(lo, hi)
+                .map(|v| v.to_string().len())
+                .max()
+                .unwrap_or(0)
+        };
+        let row_width = label_width(min[0], max[0]);
+        let col_width = label_width(min[1], max[1]);
+
+        let col_labels: Vec<Vec<char>> = num::iter::range_inclusive(min[1], max[1])
+            .map(|j| format!("{:>w$}", j, w = col_width).chars().collect())
+            .collect();
+
+        for k in 0..col_width {
+            out.push_str(&" ".repeat(row_width + 1));
+            out.extend(col_labels.iter().map(|l| l[k]));
+            out.push('\n');
+        }
+
+        let body = self.display_with_markers(&HashMap::new());
+        for (i, line) in num::iter::range_inclusive(min[0], max[0]).zip(body.lines()) {
+            out.push_str(&format!("{:>w$} {}\n", i, line, w = row_width));
+        }
+
+        out
+    }
+}
+
+impl<T, I> std::fmt::Display for Map<[I; 2], T>
+where
+    T: MapTile,
+    I: IntCoord,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}", self.display_with_markers(&HashMap::new()))
     }
 }
 
@@ -287,7 +1253,7 @@ where
     type Err = MapError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Map::read(&mut s.as_bytes())
+        Map::from_lines(s.lines())
     }
 }
 
@@ -367,6 +1333,36 @@ where
     }
 }
 
+fn offset_3d<I: IntCoord>(coord: &[I; 3], offset: &[i64; 3]) -> Option<[I; 3]> {
+    let i = I::from_i64(coord[0].to_i64()? + offset[0])?;
+    let j = I::from_i64(coord[1].to_i64()? + offset[1])?;
+    let k = I::from_i64(coord[2].to_i64()? + offset[2])?;
+    Some([i, j, k])
+}
+
+impl<T, I> Map<[I; 3], T>
+where
+    I: IntCoord,
+{
+    /// Iterate over the six neighbors of a coordinate that share a face with it and are
+    /// present in the map
+    pub fn neighbors6(&self, pos: [I; 3]) -> impl Iterator<Item = ([I; 3], &T)> + '_ {
+        const OFFSETS: [[i64; 3]; 6] = [
+            [-1, 0, 0],
+            [1, 0, 0],
+            [0, -1, 0],
+            [0, 1, 0],
+            [0, 0, -1],
+            [0, 0, 1],
+        ];
+
+        OFFSETS
+            .iter()
+            .filter_map(move |ofs| offset_3d(&pos, ofs))
+            .filter_map(move |n| self.data.get(&n).map(|t| (n, t)))
+    }
+}
+
 impl<T, I> Map<[I; 3], T>
 where
     T: MapTile,
@@ -386,6 +1382,7 @@ where
         Map {
             data,
             fixed_extent: None,
+            default: map.default.clone(),
         }
     }
 
@@ -414,18 +1411,23 @@ where
             .fixed_extent
             .map(|(min, max)| ([min[ax0], min[ax1]], [max[ax0], max[ax1]]));
 
-        Map { data, fixed_extent }
+        Map {
+            data,
+            fixed_extent,
+            default: self.default.clone(),
+        }
     }
 
     pub fn to_vecs(&self) -> Vec<Vec<Vec<Option<T>>>> {
         let (min, max) = self.get_extent();
+        let extent = self.default_extent();
 
         num::iter::range_inclusive(min[0], max[0])
             .map(|i| {
                 num::iter::range_inclusive(min[1], max[1])
                     .map(|j| {
                         num::iter::range_inclusive(min[2], max[2])
-                            .map(|k| self.data.get(&[i, j, k]).cloned())
+                            .map(|k| self.lookup(&[i, j, k], extent.as_ref()).cloned())
                             .collect()
                     })
                     .collect()
@@ -556,6 +1558,7 @@ where
         Map {
             data,
             fixed_extent: None,
+            default: map.default.clone(),
         }
     }
 
@@ -588,11 +1591,16 @@ where
             )
         });
 
-        Map { data, fixed_extent }
+        Map {
+            data,
+            fixed_extent,
+            default: self.default.clone(),
+        }
     }
 
     pub fn to_vecs(&self) -> Vec<Vec<Vec<Vec<Option<T>>>>> {
         let (min, max) = self.get_extent();
+        let extent = self.default_extent();
 
         num::iter::range_inclusive(min[0], max[0])
             .map(|i| {
@@ -601,7 +1609,7 @@ where
                         num::iter::range_inclusive(min[2], max[2])
                             .map(|k| {
                                 num::iter::range_inclusive(min[3], max[3])
-                                    .map(|l| self.data.get(&[i, j, k, l]).cloned())
+                                    .map(|l| self.lookup(&[i, j, k, l], extent.as_ref()).cloned())
                                     .collect()
                             })
                             .collect()
@@ -644,7 +1652,7 @@ where
 mod tests {
     use super::*;
 
-    #[derive(Clone, Debug, PartialEq, Eq)]
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
     struct TestTile(char);
     impl ParseMapTile for TestTile {
         fn from_char(c: char) -> Option<Self> {
@@ -655,9 +1663,9 @@ mod tests {
         }
     }
 
-    impl std::fmt::Display for TestTile {
-        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-            write!(f, "{}", self.0)
+    impl MapTile for TestTile {
+        fn to_char(&self) -> char {
+            self.0
         }
     }
 
@@ -677,6 +1685,55 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_2d_from_lines() {
+        let lines = vec!["ab ", "d e"];
+        let map = Map::<[i32; 2], TestTile>::from_lines(lines).unwrap();
+
+        assert_eq!(map.get_extent(), ([0, 0], [1, 2]));
+        assert_eq!(map.get(&[0, 1]), Some(&TestTile('b')));
+        assert_eq!(map.get(&[1, 1]), None);
+        assert_eq!(map.get(&[1, 2]), Some(&TestTile('e')));
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Wall {
+        Solid,
+        Open,
+    }
+
+    impl MapTile for Wall {
+        fn to_char(&self) -> char {
+            match self {
+                Wall::Solid => '#',
+                Wall::Open => '.',
+            }
+        }
+    }
+
+    impl ParseMapTile for Wall {
+        fn from_char(c: char) -> Option<Self> {
+            match c {
+                '#' => Some(Wall::Solid),
+                '.' => Some(Wall::Open),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_2d_blank_tiles() {
+        let map: Map<[i32; 2], Wall> = "#.#\n# #\n  .".parse().unwrap();
+        assert_eq!(map.data.len(), 6);
+        assert_eq!(map.get(&[1, 1]), None);
+        assert_eq!(map.get(&[2, 2]), Some(&Wall::Open));
+
+        match "#.\n#x".parse::<Map<[i32; 2], Wall>>() {
+            Err(MapError::UnknownTile { c, row, col }) => assert_eq!((c, row, col), ('x', 1, 1)),
+            other => panic!("Expected unknown tile error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_2d_editing() {
         let mut map: Map<[usize; 2], TestTile> = Map::new();
@@ -705,6 +1762,363 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_2d_extend() {
+        let mut map: Map<[i32; 2], TestTile> = Map::new();
+        map.extend_from(vec![([0, 0], TestTile('a')), ([1, 2], TestTile('b'))]);
+        map.extend(vec![([1, 2], TestTile('c')), ([2, 1], TestTile('d'))]);
+
+        assert_eq!(map.data.len(), 3);
+        assert_eq!(map.get(&[1, 2]), Some(&TestTile('c')));
+        assert_eq!(map.get_extent(), ([0, 0], [2, 2]));
+    }
+
+    #[test]
+    fn test_2d_rows_columns() {
+        let map: Map<[i32; 2], TestTile> = "ab\nc d".parse().unwrap();
+        let (a, b, c, d) = (TestTile('a'), TestTile('b'), TestTile('c'), TestTile('d'));
+
+        assert_eq!(
+            map.rows(),
+            vec![
+                vec![Some(&a), Some(&b), None],
+                vec![Some(&c), None, Some(&d)]
+            ]
+        );
+        assert_eq!(
+            map.columns(),
+            vec![
+                vec![Some(&a), Some(&c)],
+                vec![Some(&b), None],
+                vec![None, Some(&d)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_2d_is_dense() {
+        let mut map: Map<[i32; 2], TestTile> = "abc\ndef\nghi".parse().unwrap();
+        assert!(map.is_dense());
+
+        map.remove(&[1, 1]);
+        assert!(!map.is_dense());
+    }
+
+    #[test]
+    fn test_2d_corners_edges() {
+        let map: Map<[i32; 2], TestTile> = "abcd\nefgh\nijkl\nmnop".parse().unwrap();
+
+        assert_eq!(map.corner_coords(), [[0, 0], [0, 3], [3, 0], [3, 3]]);
+
+        let edges = map.edge_coords();
+        assert_eq!(edges.len(), 12);
+        assert!(!edges.contains(&[1, 1]));
+        assert!(!edges.contains(&[2, 2]));
+        assert!(edges.contains(&[0, 2]));
+        assert!(edges.contains(&[3, 1]));
+
+        let row: Map<[i32; 2], TestTile> = "abc".parse().unwrap();
+        assert_eq!(row.edge_coords(), vec![[0, 0], [0, 1], [0, 2]]);
+    }
+
+    #[test]
+    fn test_2d_simulate_blinker() {
+        let start: Map<[i32; 2], TestTile> = ".....\n..#..\n..#..\n..#..\n.....".parse().unwrap();
+        let rule = |map: &Map<[i32; 2], TestTile>, pos: [i32; 2]| {
+            let alive = map.count_neighbors(pos, Connectivity::Eight, |t| t.0 == '#');
+            match (map.get(&pos), alive) {
+                (Some(TestTile('#')), 2) | (_, 3) => TestTile('#'),
+                _ => TestTile('.'),
+            }
+        };
+
+        let one = simulate(start.clone(), 1, rule);
+        assert_eq!(format!("{}", one), ".....\n.....\n.###.\n.....\n.....\n");
+
+        let two = simulate(start.clone(), 2, rule);
+        assert_eq!(two, start);
+    }
+
+    #[test]
+    fn test_2d_with_default() {
+        let mut map: Map<[i32; 2], TestTile> = Map::with_default(TestTile('.'));
+        map.set([0, 0], TestTile('#'));
+        map.set([1, 2], TestTile('#'));
+
+        assert_eq!(map.get(&[0, 0]), Some(&TestTile('#')));
+        assert_eq!(map.get(&[0, 1]), Some(&TestTile('.')));
+        assert_eq!(map.get_cloned(&[1, 1]), Some(TestTile('.')));
+
+        // outside of the extent, there is no default
+        assert_eq!(map.get(&[-1, 0]), None);
+        assert_eq!(map.get(&[1, 3]), None);
+
+        // the caller's fallback wins over the map's default
+        assert_eq!(map.get_or(&[0, 1], TestTile('x')), TestTile('x'));
+        assert_eq!(map.get_or(&[0, 0], TestTile('x')), TestTile('#'));
+
+        // the default does not count as a tile, but all lookups see it
+        assert_eq!(map.data.len(), 2);
+        assert_eq!(format!("{}", map), "#..\n..#\n");
+        assert_eq!(
+            map.rows()[0],
+            vec![
+                Some(&TestTile('#')),
+                Some(&TestTile('.')),
+                Some(&TestTile('.'))
+            ]
+        );
+        assert_eq!(
+            map.neighbors_with([0, 0], Connectivity::Four.offsets())
+                .count(),
+            2
+        );
+        assert_eq!(map.neighbors4_opt([0, 0])[0], ([-1, 0], None));
+
+        let empty: Map<[i32; 2], TestTile> = Map::with_default(TestTile('.'));
+        assert_eq!(empty.get(&[0, 0]), None);
+    }
+
+    #[test]
+    fn test_2d_get_or() {
+        let map: Map<[i32; 2], TestTile> = "ab\nc".parse().unwrap();
+
+        assert_eq!(map.get_cloned(&[0, 1]), Some(TestTile('b')));
+        assert_eq!(map.get_cloned(&[1, 1]), None);
+        assert_eq!(map.get_or(&[1, 0], TestTile('#')), TestTile('c'));
+        assert_eq!(map.get_or(&[1, 1], TestTile('#')), TestTile('#'));
+    }
+
+    #[test]
+    fn test_2d_symmetry() {
+        let map: Map<[i32; 2], TestTile> = "#.#\n.#.\n..."
+            .parse::<Map<[i32; 2], TestTile>>()
+            .unwrap()
+            .translate([-1, -1]);
+        assert!(map.has_horizontal_symmetry());
+        assert!(!map.has_vertical_symmetry());
+
+        let map: Map<[i32; 2], TestTile> = "ab\ncd\nab".parse().unwrap();
+        assert!(!map.has_horizontal_symmetry());
+        assert!(map.has_vertical_symmetry());
+
+        // a missing tile breaks the symmetry
+        let map: Map<[i32; 2], TestTile> = "aa \naaa".parse().unwrap();
+        assert!(!map.has_horizontal_symmetry());
+        assert!(!map.has_vertical_symmetry());
+    }
+
+    #[test]
+    fn test_2d_symmetry_unsigned() {
+        let map: Map<[usize; 2], TestTile> = "#.#\n.#.\n...".parse().unwrap();
+        assert!(map.has_horizontal_symmetry());
+        assert!(!map.has_vertical_symmetry());
+
+        let shifted = map.translate([4, 2]);
+        assert!(shifted.has_horizontal_symmetry());
+        assert!(!shifted.has_vertical_symmetry());
+
+        // cells outside of a fixed extent have no mirror image
+        let mut clipped = map.clone();
+        clipped.fixed_extent = Some(([0, 0], [0, 0]));
+        assert!(!clipped.has_horizontal_symmetry());
+        assert!(!clipped.has_vertical_symmetry());
+    }
+
+    #[test]
+    fn test_2d_normalize() {
+        let original: Map<[i32; 2], TestTile> = "ab\n c".parse().unwrap();
+        let mut map = original.translate([-3, 2]);
+        assert_eq!(map.get_extent(), ([-3, 2], [-2, 3]));
+
+        assert_eq!(map.normalize(), [3, -2]);
+        assert_eq!(map, original);
+        assert_eq!(map.normalize(), [0, 0]);
+    }
+
+    #[test]
+    fn test_2d_transpose() {
+        let map: Map<[i32; 2], TestTile> = "abc\nd f".parse().unwrap();
+        let transposed = map.transpose();
+
+        assert_eq!(format!("{}", transposed), "ad\nb \ncf\n");
+        assert_eq!(transposed.transpose(), map);
+
+        let shifted = map.translate([3, -2]);
+        assert_eq!(shifted.transpose().get_extent(), ([3, -2], [5, -1]));
+        assert_eq!(shifted.transpose().transpose(), shifted);
+    }
+
+    #[test]
+    fn test_2d_transpose_unsigned() {
+        let map: Map<[usize; 2], TestTile> = "abc\nd f".parse().unwrap();
+        assert_eq!(format!("{}", map.transpose()), "ad\nb \ncf\n");
+        assert_eq!(map.transpose().transpose(), map);
+
+        let shifted = map.translate([1, 3]);
+        assert_eq!(shifted.transpose().get_extent(), ([1, 3], [3, 4]));
+        assert_eq!(shifted.transpose().transpose(), shifted);
+    }
+
+    #[test]
+    fn test_2d_flood_count() {
+        let map: Map<[i32; 2], TestTile> = "..#..\n..#..\n###..\n.....".parse().unwrap();
+        let open = |t: &TestTile| t.0 == '.';
+
+        assert_eq!(map.flood_count([0, 0], open), 4);
+        assert_eq!(map.flood_count([0, 4], open), 11);
+        assert_eq!(map.flood_count([0, 2], open), 0);
+        assert_eq!(map.flood_count([9, 9], open), 0);
+        assert_eq!(map.flood_count([0, 2], |t| t.0 == '#'), 5);
+
+        // tiles outside of a fixed extent are still counted
+        let mut map = map;
+        map.fixed_extent = Some(([0, 0], [1, 1]));
+        assert_eq!(map.flood_count([3, 4], open), 11);
+        assert_eq!(map.flood_count([0, 0], open), 4);
+
+        let map: Map<[u8; 2], TestTile> = "###\n#..\n#..".parse().unwrap();
+        assert_eq!(map.flood_count([1, 1], open), 4);
+    }
+
+    #[test]
+    fn test_2d_exterior_cells() {
+        let map: Map<[i32; 2], TestTile> = ".....\n.###.\n.#.#.\n.###.\n.....".parse().unwrap();
+        let exterior = map.exterior_cells(|t| t.0 == '.');
+
+        assert_eq!(exterior.len(), 16);
+        assert!(exterior.contains(&[0, 0]));
+        assert!(exterior.contains(&[4, 2]));
+        assert!(!exterior.contains(&[2, 2]));
+        assert!(!exterior.contains(&[1, 1]));
+    }
+
+    #[test]
+    fn test_2d_ensure_filled() {
+        let mut map: Map<[i32; 2], TestTile> = "ab\n c".parse().unwrap();
+        map.ensure_filled([-1, 0], [1, 2], TestTile('.'));
+
+        assert_eq!(format!("{}", map), "...\nab.\n.c.\n");
+        assert_eq!(map.data.len(), 9);
+
+        // existing tiles, also outside of the box, are left alone
+        let mut map: Map<[i32; 2], TestTile> = "abc".parse().unwrap();
+        map.ensure_filled([0, 1], [0, 1], TestTile('.'));
+        assert_eq!(format!("{}", map), "abc\n");
+    }
+
+    #[test]
+    fn test_2d_find() {
+        let map: Map<[i32; 2], TestTile> = "..#.\n.S..\n#..#".parse().unwrap();
+
+        assert_eq!(map.find(|t| t.0 == 'S'), Some([1, 1]));
+        assert_eq!(map.find(|t| t.0 == '#'), Some([0, 2]));
+        assert_eq!(map.find(|t| t.0 == 'x'), None);
+        assert_eq!(
+            map.find_all_sorted(|t| t.0 == '#'),
+            vec![[0, 2], [2, 0], [2, 3]]
+        );
+    }
+
+    #[test]
+    fn test_2d_find_fixed_point_or_cycle() {
+        let blinker: Map<[i32; 2], TestTile> = ".#.\n.#.\n.#.".parse().unwrap();
+        let rotate = |map: &Map<[i32; 2], TestTile>| map.transpose();
+
+        assert_eq!(
+            find_fixed_point_or_cycle(blinker.clone(), rotate, 10),
+            Some(CycleInfo {
+                start: 0,
+                period: 2
+            })
+        );
+        assert_eq!(find_fixed_point_or_cycle(blinker.clone(), rotate, 1), None);
+
+        // shifting the map never repeats, but clamping it to a fixed row settles down
+        let shift = |map: &Map<[i32; 2], TestTile>| map.translate([1, 0]);
+        assert_eq!(find_fixed_point_or_cycle(blinker.clone(), shift, 20), None);
+
+        let settle = |map: &Map<[i32; 2], TestTile>| {
+            let shifted = map.translate([1, 0]);
+            let mut out = Map::new();
+            for (k, t) in shifted.data {
+                out.set([k[0].min(4), k[1]], t);
+            }
+            out
+        };
+        assert_eq!(
+            find_fixed_point_or_cycle(blinker, settle, 20),
+            Some(CycleInfo {
+                start: 4,
+                period: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_2d_neighbors_diagonal() {
+        let map: Map<[i32; 2], TestTile> = "abc\ndef\nghi".parse().unwrap();
+
+        let tiles: String = map.neighbors_diagonal([1, 1]).map(|(_, t)| t.0).collect();
+        assert_eq!(tiles, "acgi");
+
+        let corner: Vec<[i32; 2]> = map.neighbors_diagonal([0, 0]).map(|(p, _)| p).collect();
+        assert_eq!(corner, vec![[1, 1]]);
+    }
+
+    #[test]
+    fn test_2d_count_neighbors() {
+        let map: Map<[i32; 2], TestTile> = "#.#\n.##\n##.".parse().unwrap();
+        let alive = |t: &TestTile| t.0 == '#';
+
+        assert_eq!(map.count_neighbors([1, 1], Connectivity::Eight, alive), 5);
+        assert_eq!(map.count_neighbors([1, 1], Connectivity::Four, alive), 2);
+        assert_eq!(map.count_neighbors([0, 0], Connectivity::Eight, alive), 1);
+    }
+
+    #[test]
+    fn test_2d_neighbors4_opt() {
+        let map: Map<[i32; 2], TestTile> = "abc\ndef\nghi".parse().unwrap();
+
+        let edge = map.neighbors4_opt([0, 1]);
+        assert_eq!(
+            edge.iter().map(|(ofs, _)| *ofs).collect::<Vec<_>>(),
+            vec![[-1, 0], [1, 0], [0, -1], [0, 1]]
+        );
+        assert_eq!(
+            edge.iter().map(|(_, t)| t.map(|t| t.0)).collect::<Vec<_>>(),
+            vec![None, Some('e'), Some('a'), Some('c')]
+        );
+    }
+
+    #[test]
+    fn test_2d_within_manhattan() {
+        let map: Map<[i32; 2], TestTile> = "abcd\nefgh\nijkl\nmnop".parse().unwrap();
+
+        let tiles: String = map.within_manhattan([0, 0], 2).map(|(_, t)| t.0).collect();
+        assert_eq!(tiles, "abcefi");
+
+        let tiles: String = map.within_manhattan([2, 2], 1).map(|(_, t)| t.0).collect();
+        assert_eq!(tiles, "gjklo");
+
+        assert_eq!(map.within_manhattan([0, 0], 0).count(), 1);
+        assert_eq!(map.within_manhattan([9, 9], 2).count(), 0);
+    }
+
+    #[test]
+    fn test_2d_push_row() {
+        let mut map: Map<[i32; 2], TestTile> = Map::new();
+        map.push_row(0, "ab".chars().map(TestTile));
+        map.push_row(1, "cde".chars().map(TestTile));
+        map.push_row(2, "f".chars().map(TestTile));
+
+        assert_eq!(map.get_extent(), ([0, 0], [2, 2]));
+        assert_eq!(map.get(&[0, 1]), Some(&TestTile('b')));
+        assert_eq!(map.get(&[1, 2]), Some(&TestTile('e')));
+        assert_eq!(map.get(&[2, 0]), Some(&TestTile('f')));
+        assert_eq!(map.get(&[2, 1]), None);
+    }
+
     #[test]
     fn test_2d_display() {
         let map_string = "ab \nd e";
@@ -715,4 +2129,326 @@ mod tests {
         let map2: Map<[usize; 2], TestTile> = Map::new();
         assert_eq!(format!("{}", map2), "");
     }
+
+    #[test]
+    fn test_2d_display_with_markers() {
+        let map: Map<[i32; 2], TestTile> = "...\n.#.\n...".parse().unwrap();
+
+        let mut markers = HashMap::new();
+        markers.insert([0, 2], 'G');
+        markers.insert([2, 0], '_');
+        markers.insert([5, 5], 'X');
+
+        assert_eq!(map.display_with_markers(&markers), "..G\n.#.\n_..\n");
+        assert_eq!(
+            map.display_with_markers(&HashMap::new()),
+            format!("{}", map)
+        );
+    }
+
+    #[test]
+    fn test_2d_to_string_with_axes() {
+        let map: Map<[i32; 2], TestTile> = "ab\ncd".parse().unwrap();
+        assert_eq!(map.to_string_with_axes(), "  01\n0 ab\n1 cd\n");
+
+        let mut wide: Map<[i32; 2], TestTile> = Map::new();
+        wide.push_row(9, "abcdefghijkl".chars().map(TestTile));
+        wide.push_row(10, "mnopqrstuvwx".chars().map(TestTile));
+        assert_eq!(
+            wide.to_string_with_axes(),
+            "             11\n   012345678901\n 9 abcdefghijkl\n10 mnopqrstuvwx\n"
+        );
+    }
+
+    #[test]
+    fn test_2d_bounds_where() {
+        let map: Map<[i32; 2], TestTile> = "....\n.x..\n....\n...x".parse().unwrap();
+
+        assert_eq!(map.bounds_where(|t| t.0 == 'x'), Some(([1, 1], [3, 3])));
+        assert_eq!(map.bounds_where(|t| t.0 == 'y'), None);
+    }
+
+    #[test]
+    fn test_2d_path_exists() {
+        let map: Map<[i32; 2], TestTile> = "..#..\n..#..\n.##..".parse().unwrap();
+        let passable = |t: &TestTile| t.0 != '#';
+
+        assert!(map.path_exists([0, 0], [2, 0], Connectivity::Four, passable));
+        assert!(map.path_exists([0, 3], [2, 4], Connectivity::Four, passable));
+        assert!(!map.path_exists([0, 0], [0, 4], Connectivity::Four, passable));
+    }
+
+    #[test]
+    fn test_2d_distances_from() {
+        let map: Map<[i32; 2], TestTile> = "...\n.#.\n...".parse().unwrap();
+        let dist = map.distances_from([0, 0], Connectivity::Four, |t| t.0 != '#');
+
+        assert_eq!(dist.len(), 8);
+        assert_eq!(dist[&[0, 0]], 0);
+        assert_eq!(dist[&[0, 2]], 2);
+        assert_eq!(dist[&[2, 2]], 4);
+        assert_eq!(dist.get(&[1, 1]), None);
+    }
+
+    #[test]
+    fn test_2d_connectivity() {
+        let map: Map<[i32; 2], TestTile> = ".#...\n.#.#.\n...#.".parse().unwrap();
+        let passable = |t: &TestTile| t.0 != '#';
+
+        assert_eq!(
+            map.shortest_path_len([0, 0], [0, 4], Connectivity::Four, passable),
+            Some(8)
+        );
+        assert_eq!(
+            map.shortest_path_len([0, 0], [0, 4], Connectivity::Eight, passable),
+            Some(5)
+        );
+
+        let dist = map.distances_from([2, 2], Connectivity::Four, passable);
+        assert_eq!(dist[&[0, 4]], 4);
+        let dist = map.distances_from([2, 2], Connectivity::Eight, passable);
+        assert_eq!(dist[&[0, 4]], 3);
+    }
+
+    #[test]
+    fn test_2d_dijkstra_grid() {
+        let map: Map<[i32; 2], TestTile> = "S9E\n1#1\n111".parse().unwrap();
+        let cost = |t: &TestTile| match t.0 {
+            '#' => None,
+            c => Some(c.to_digit(10).unwrap_or(1)),
+        };
+
+        let (total, path) = map
+            .dijkstra_grid([0, 0], [0, 2], Connectivity::Four, cost)
+            .unwrap();
+
+        assert_eq!(total, 6);
+        assert_eq!(
+            path,
+            vec![[0, 0], [1, 0], [2, 0], [2, 1], [2, 2], [1, 2], [0, 2]]
+        );
+
+        assert_eq!(
+            map.dijkstra_grid([0, 0], [1, 1], Connectivity::Four, cost),
+            None
+        );
+    }
+
+    #[test]
+    fn test_2d_neighbors_with() {
+        let map: Map<[i32; 2], TestTile> = "abc\ndef\nghi".parse().unwrap();
+        let knight_moves = [
+            [-2, -1],
+            [-2, 1],
+            [-1, -2],
+            [-1, 2],
+            [1, -2],
+            [1, 2],
+            [2, -1],
+            [2, 1],
+        ];
+
+        let mut reached: Vec<char> = map
+            .neighbors_with([0, 0], &knight_moves)
+            .map(|(_, t)| t.0)
+            .collect();
+        reached.sort();
+        assert_eq!(reached, vec!['f', 'h']);
+
+        assert_eq!(map.neighbors_with([1, 1], &knight_moves).count(), 0);
+    }
+
+    #[test]
+    fn test_iter_sorted_by() {
+        let map: Map<[i32; 2], TestTile> = "abc\ndef".parse().unwrap();
+        let dist = |c: &[i32; 2]| c[0].abs() + c[1].abs();
+
+        let order: String = map
+            .iter_sorted_by(|a, b| dist(a).cmp(&dist(b)).then_with(|| b.cmp(a)))
+            .map(|(_, t)| t.0)
+            .collect();
+        assert_eq!(order, "adbecf");
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let map: Map<[i32; 2], TestTile> = "ab\nc d".parse().unwrap();
+
+        let mut tiles = Vec::new();
+        for (pos, tile) in &map {
+            tiles.push((*pos, tile.0));
+        }
+        assert_eq!(
+            tiles,
+            vec![([0, 0], 'a'), ([0, 1], 'b'), ([1, 0], 'c'), ([1, 2], 'd')]
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut map: Map<[i32; 2], u8> = Map::new();
+        map.push_row(0, vec![1, 2, 3]);
+        map.push_row(1, vec![9]);
+
+        map.apply(|v| (v + 1) % 10);
+        assert_eq!(
+            map.rows(),
+            vec![
+                vec![Some(&2), Some(&3), Some(&4)],
+                vec![Some(&0), None, None]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_where() {
+        let map: Map<[i32; 2], TestTile> = "#.#\n..#".parse().unwrap();
+        assert_eq!(map.count_where(|_, t| t.0 == '#'), 3);
+        assert_eq!(map.count_where(|c, t| c[0] == 1 && t.0 == '.'), 2);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_count_where() {
+        let mut map: Map<[i32; 2], bool> = Map::new();
+        for i in 0..500 {
+            map.push_row(i, (0..500).map(|j| (i * j) % 7 == 3));
+        }
+
+        let serial = map.count_where(|_, t| *t);
+        assert!(serial > 0);
+        assert_eq!(map.par_count_where(|_, t| *t), serial);
+        assert_eq!(map.par_count_where(|c, _| c[0] < 10), 5000);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_distances_from() {
+        let mut map: Map<[i32; 2], bool> = Map::new();
+        for i in 0..300 {
+            map.push_row(i, (0..300).map(|j| (i * 7 + j * 13) % 11 != 0));
+        }
+
+        for connectivity in &[Connectivity::Four, Connectivity::Eight] {
+            let serial = map.distances_from([1, 1], *connectivity, |t| *t);
+            assert!(serial.len() > 10_000);
+            assert_eq!(
+                map.par_distances_from([1, 1], *connectivity, |t| *t),
+                serial
+            );
+        }
+    }
+
+    #[test]
+    fn test_3d() {
+        let mut map: Map<[i32; 3], TestTile> = Map::new();
+        map.ensure_filled([0, 0, 0], [1, 2, 2], TestTile('.'));
+        map.set([0, 1, 1], TestTile('#'));
+        map.remove(&[1, 1, 1]);
+        assert_eq!(map.get_extent(), ([0, 0, 0], [1, 2, 2]));
+
+        let mut neighbors: Vec<[i32; 3]> = map.neighbors6([0, 1, 1]).map(|(p, _)| p).collect();
+        neighbors.sort();
+        assert_eq!(neighbors, vec![[0, 0, 1], [0, 1, 0], [0, 1, 2], [0, 2, 1]]);
+        assert_eq!(map.neighbors6([0, 0, 0]).count(), 3);
+        assert_eq!(map.neighbors6([1, 1, 2]).count(), 3);
+        assert_eq!(
+            map.neighbors6([0, 0, 1])
+                .filter(|(_, t)| t.0 == '#')
+                .count(),
+            1
+        );
+
+        assert_eq!(
+            format!("{}", map),
+            "Layer 0 =========\n...\n.#.\n...\n\nLayer 1 =========\n...\n. .\n...\n\n"
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut a: Map<[i32; 2], TestTile> = Map::new();
+        a.set([0, 0], TestTile('x'));
+        a.set([3, 1], TestTile('y'));
+        a.set([1, 2], TestTile('z'));
+
+        let mut b: Map<[i32; 2], TestTile> = Map::new();
+        b.set([1, 2], TestTile('z'));
+        b.set([0, 0], TestTile('x'));
+        b.set([3, 1], TestTile('y'));
+        assert_eq!(a.checksum(), b.checksum());
+
+        b.set([3, 1], TestTile('z'));
+        assert_ne!(a.checksum(), b.checksum());
+
+        // swapping tiles between coordinates changes the checksum
+        let mut c = a.clone();
+        c.set([0, 0], TestTile('y'));
+        c.set([3, 1], TestTile('x'));
+        assert_ne!(a.checksum(), c.checksum());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let map: Map<[i32; 2], TestTile> = ".^^.\n^ .^".parse().unwrap();
+        let counts = map.histogram();
+
+        assert_eq!(counts[&TestTile('.')], 3);
+        assert_eq!(counts[&TestTile('^')], 4);
+        assert_eq!(counts.values().sum::<usize>(), map.data.len());
+    }
+
+    #[test]
+    fn test_2d_flood_fill_set() {
+        let mut map: Map<[i32; 2], TestTile> = ".....\n.###.\n.#.#.\n.###.\n.....".parse().unwrap();
+
+        map.flood_fill_set([2, 2], TestTile('o'));
+        assert_eq!(format!("{}", map), ".....\n.###.\n.#o#.\n.###.\n.....\n");
+
+        map.flood_fill_set([0, 0], TestTile('~'));
+        assert_eq!(format!("{}", map), "~~~~~\n~###~\n~#o#~\n~###~\n~~~~~\n");
+
+        // filling with the same tile does nothing
+        let before = map.clone();
+        map.flood_fill_set([0, 0], TestTile('~'));
+        assert_eq!(map, before);
+    }
+
+    #[test]
+    fn test_2d_translate() {
+        let map: Map<[i32; 2], TestTile> = "ab\ncd".parse().unwrap();
+        let moved = map.translate([3, -2]);
+
+        assert_eq!(moved.get(&[4, -1]), Some(&TestTile('d')));
+        assert_eq!(moved.get(&[1, 1]), None);
+        assert_eq!(moved.get_extent(), ([3, -2], [4, -1]));
+
+        let mut combined = map.clone();
+        combined.overlay(&map.translate([0, 2]));
+        assert_eq!(format!("{}", combined), "abab\ncdcd\n");
+
+        // tiles that would move to negative unsigned coordinates are dropped
+        let unsigned: Map<[usize; 2], TestTile> = "ab\ncd".parse().unwrap();
+        assert_eq!(unsigned.translate([0, -1]).data.len(), 2);
+    }
+
+    #[test]
+    fn test_char_roundtrip() {
+        assert!(roundtrips_char(&TestTile('a')));
+        assert!(!roundtrips_char(&TestTile(' ')));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut map: Map<[i32; 2], char> = Map::new();
+        map.set([0, 0], 'a');
+        map.set([-1, 3], 'b');
+        map.set([2, -5], 'c');
+
+        let json = serde_json::to_string(&map).unwrap();
+        let restored: Map<[i32; 2], char> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored, map);
+    }
 }
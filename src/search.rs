@@ -0,0 +1,132 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Find the cost of the cheapest path from `start` to a state matching `is_goal` using A*.
+///
+/// `neighbors` yields all successor states together with the cost of moving there, and
+/// `heuristic` estimates the remaining cost. The result is only guaranteed to be optimal
+/// if the heuristic never overestimates. Paths that would cost more than `max_cost` are
+/// not explored.
+pub fn astar<S, N, I, H, G>(
+    start: S,
+    neighbors: N,
+    heuristic: H,
+    is_goal: G,
+    max_cost: Option<usize>,
+) -> Option<usize>
+where
+    S: Clone + Eq + std::hash::Hash,
+    N: FnMut(&S) -> I,
+    I: IntoIterator<Item = (S, usize)>,
+    H: Fn(&S) -> usize,
+    G: Fn(&S) -> bool,
+{
+    astar_path(start, neighbors, heuristic, is_goal, max_cost).map(|(cost, _)| cost)
+}
+
+/// Like `astar`, but also return all states along the cheapest path, from `start` to the
+/// goal state
+pub fn astar_path<S, N, I, H, G>(
+    start: S,
+    mut neighbors: N,
+    heuristic: H,
+    is_goal: G,
+    max_cost: Option<usize>,
+) -> Option<(usize, Vec<S>)>
+where
+    S: Clone + Eq + std::hash::Hash,
+    N: FnMut(&S) -> I,
+    I: IntoIterator<Item = (S, usize)>,
+    H: Fn(&S) -> usize,
+    G: Fn(&S) -> bool,
+{
+    // states are kept in a list so that the queue only needs to order by cost. Each
+    // entry remembers the index of the entry it was reached from.
+    let mut states: Vec<(S, usize, Option<usize>)> = Vec::new();
+    let mut best: HashMap<S, usize> = HashMap::new();
+    let mut queue: BinaryHeap<(Reverse<usize>, usize)> = BinaryHeap::new();
+
+    best.insert(start.clone(), 0);
+    queue.push((Reverse(heuristic(&start)), 0));
+    states.push((start, 0, None));
+
+    while let Some((_, idx)) = queue.pop() {
+        let (state, cost, _) = states[idx].clone();
+
+        // skip queue entries that have been superseded by a cheaper path
+        if best[&state] < cost {
+            continue;
+        }
+
+        if is_goal(&state) {
+            let mut path = vec![state];
+            let mut parent = states[idx].2;
+            while let Some(p) = parent {
+                path.push(states[p].0.clone());
+                parent = states[p].2;
+            }
+            path.reverse();
+            return Some((cost, path));
+        }
+
+        for (next, step_cost) in neighbors(&state) {
+            let next_cost = cost + step_cost;
+
+            if max_cost.map(|m| next_cost > m).unwrap_or(false) {
+                continue;
+            }
+
+            if best.get(&next).map(|c| *c <= next_cost).unwrap_or(false) {
+                continue;
+            }
+
+            best.insert(next.clone(), next_cost);
+            queue.push((Reverse(next_cost + heuristic(&next)), states.len()));
+            states.push((next, next_cost, Some(idx)));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_astar_line() {
+        // walk along a number line from 0 to 10, with a shortcut from 3 to 8
+        let neighbors = |n: &i32| {
+            let mut out = vec![(n - 1, 1), (n + 1, 1)];
+            if *n == 3 {
+                out.push((8, 2));
+            }
+            out
+        };
+        let heuristic = |n: &i32| (10 - n).unsigned_abs() as usize / 2;
+
+        assert_eq!(astar(0, neighbors, heuristic, |n| *n == 10, None), Some(7));
+        assert_eq!(astar(0, neighbors, heuristic, |n| *n == 10, Some(6)), None);
+    }
+
+    #[test]
+    fn test_astar_path() {
+        let neighbors = |n: &i32| {
+            let mut out = vec![(n - 1, 1), (n + 1, 1)];
+            if *n == 3 {
+                out.push((8, 2));
+            }
+            out
+        };
+        let heuristic = |n: &i32| (10 - n).unsigned_abs() as usize / 2;
+
+        assert_eq!(
+            astar_path(0, neighbors, heuristic, |n| *n == 10, None),
+            Some((7, vec![0, 1, 2, 3, 8, 9, 10]))
+        );
+        assert_eq!(
+            astar_path(5, neighbors, heuristic, |n| *n == 5, None),
+            Some((0, vec![5]))
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Checks that the asmbunny VM works when the library is built without the `std` feature.
+//! Run with `cargo test --no-default-features --test no_std`.
+//! This runs on the host, where `std` is still linked in. Whether the library really
+//! builds without `std` is checked by building it for a target that has no `std`, e.g.
+//! `cargo build --lib --no-default-features --target thumbv7em-none-eabi`.
+#![cfg(not(feature = "std"))]
+
+use aoc2016::asmbunny::{Instruction, State, StepResult};
+
+#[test]
+fn test_no_std_vm() {
+    let instructions: Vec<Instruction> = "cpy 3 a\ndec a\ninc b\njnz a -2\nout b"
+        .lines()
+        .map(|l| l.parse().unwrap())
+        .collect();
+
+    let mut state = State::from_instructions(instructions);
+    let mut outputs = Vec::new();
+    loop {
+        match state.step() {
+            StepResult::OutOfProgram => break,
+            StepResult::OkOutput { out } => outputs.push(out),
+            _ => {}
+        }
+    }
+
+    assert_eq!(outputs, vec![3]);
+}